@@ -5,21 +5,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map_err(|e| format!("failed to locate vendored protoc: {}", e))?;
     std::env::set_var("PROTOC", protoc);
 
-    // Compile the Modal proto into Rust types using tonic/prost.
+    // Compile the Modal protos into Rust types using tonic/prost.
     // Resolve paths relative to the crate manifest dir so the build works
     // whether the crate is used from a git dependency or a local path.
+    // The router protos import "modal_proto/api.proto", so the manifest dir is
+    // the include root and every proto is named relative to it.
     let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")?;
-    let proto_dir = std::path::Path::new(&manifest_dir).join("modal_proto");
-    let api_proto = proto_dir.join("api.proto");
+    let root = std::path::Path::new(&manifest_dir);
+    let proto_dir = root.join("modal_proto");
+    let protos = [
+        proto_dir.join("api.proto"),
+        proto_dir.join("sandbox_router.proto"),
+        proto_dir.join("task_command_router.proto"),
+    ];
 
     // Tell cargo when to rerun the build script.
-    println!("cargo:rerun-if-changed={}", api_proto.display());
+    for proto in &protos {
+        println!("cargo:rerun-if-changed={}", proto.display());
+    }
     println!("cargo:rerun-if-changed={}", proto_dir.display());
 
+    let protos = protos
+        .iter()
+        .map(|p| p.to_str().expect("invalid proto path"))
+        .collect::<Vec<_>>();
     tonic_build::configure()
         .build_server(false)
-        .compile(&[api_proto.to_str().expect("invalid proto path")], &[
-            proto_dir.to_str().expect("invalid proto dir"),
-        ])?;
+        .compile(&protos, &[root.to_str().expect("invalid proto dir")])?;
     Ok(())
 }
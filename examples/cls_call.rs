@@ -1,5 +1,5 @@
 use anyhow::Result;
-use modal::{Cls, ModalClient};
+use modal::ModalClient;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -13,8 +13,7 @@ async fn main() -> Result<()> {
     // Initialize client from modal profile (~/.modal.toml) or environment
     let mut client = ModalClient::from_env().await?;
 
-    let app_name =
-        std::env::var("MODAL_APP").unwrap_or_else(|_| "MyApp".to_string());
+    let app_name = std::env::var("MODAL_APP").unwrap_or_else(|_| "MyApp".to_string());
     let class_name = std::env::var("MODAL_CLASS").unwrap_or_else(|_| "MyClass".to_string());
 
     println!("Looking up class {}::{}", app_name, class_name);
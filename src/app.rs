@@ -0,0 +1,276 @@
+use anyhow::{anyhow, Result};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+
+/// App management API, returned by `ModalClient::apps`.
+#[derive(Clone)]
+pub struct Apps {
+    client: ModalClient,
+}
+
+/// Lifecycle state of an App.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppState {
+    Unspecified,
+    /// Stopped when the client that created it disconnects.
+    Ephemeral,
+    Detached,
+    Deployed,
+    Stopping,
+    Stopped,
+    Initializing,
+    Disabled,
+    DetachedDisconnected,
+}
+
+/// An App in the environment, as returned by `Apps::list`.
+#[derive(Clone, Debug)]
+pub struct AppListItem {
+    pub app_id: String,
+    pub name: String,
+    pub description: String,
+    pub state: AppState,
+    /// Creation time in seconds since the Unix epoch.
+    pub created_at: f64,
+    /// Stop time in seconds since the Unix epoch, if the App has stopped.
+    pub stopped_at: Option<f64>,
+    pub running_tasks: u32,
+}
+
+/// One deployment of an App, as returned by `Apps::deployment_history`.
+#[derive(Clone, Debug)]
+pub struct AppDeployment {
+    pub version: u32,
+    pub client_version: String,
+    /// Deployment time in seconds since the Unix epoch.
+    pub deployed_at: f64,
+    pub deployed_by: String,
+    pub tag: String,
+    /// The version this deployment rolled back to, or 0 if it was not a rollback.
+    pub rollback_version: u32,
+    /// Whether `Apps::rollback` may return to this version.
+    pub rollback_allowed: bool,
+    pub commit: Option<CommitInfo>,
+}
+
+/// Source control state of a deployment.
+#[derive(Clone, Debug)]
+pub struct CommitInfo {
+    pub vcs: String,
+    pub branch: String,
+    pub commit_hash: String,
+    /// Commit time in seconds since the Unix epoch.
+    pub commit_timestamp: i64,
+    /// Whether the working tree had uncommitted changes.
+    pub dirty: bool,
+    pub author_name: String,
+    pub author_email: String,
+    pub repo_url: String,
+}
+
+impl ModalClient {
+    /// Look up an App by name and return its id, creating it if `create_if_missing` is set.
+    pub async fn app_from_name(&mut self, name: &str, create_if_missing: bool) -> Result<String> {
+        let object_creation_type = if create_if_missing {
+            client::ObjectCreationType::CreateIfMissing
+        } else {
+            client::ObjectCreationType::Unspecified
+        };
+        let req_msg = client::AppGetOrCreateRequest {
+            app_name: name.to_string(),
+            environment_name: String::new(),
+            object_creation_type: object_creation_type as i32,
+        };
+        let req = self.make_request(req_msg);
+        let resp = self.stub.app_get_or_create(req).await?.into_inner();
+        if resp.app_id.is_empty() {
+            return Err(anyhow!("app not found"));
+        }
+        Ok(resp.app_id)
+    }
+
+    /// Access the App management API.
+    pub fn apps(&self) -> Apps {
+        Apps {
+            client: self.clone(),
+        }
+    }
+}
+
+impl Apps {
+    /// List the Apps in the default environment.
+    pub async fn list(&mut self) -> Result<Vec<AppListItem>> {
+        let req_msg = client::AppListRequest {
+            environment_name: String::new(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.app_list(req).await?.into_inner();
+        Ok(resp
+            .apps
+            .into_iter()
+            .map(|item| AppListItem {
+                state: item.state().into(),
+                app_id: item.app_id,
+                name: item.name,
+                description: item.description,
+                created_at: item.created_at,
+                stopped_at: Some(item.stopped_at).filter(|t| *t > 0.0),
+                running_tasks: item.n_running_tasks.max(0) as u32,
+            })
+            .collect())
+    }
+
+    /// Look up a running or deployed App by name, returning its id.
+    pub async fn lookup(&mut self, name: &str) -> Result<String> {
+        let req_msg = client::AppLookupRequest {
+            app_name: name.to_string(),
+            environment_name: String::new(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = match self.client.stub.app_lookup(req).await {
+            Ok(resp) => resp.into_inner(),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                return Err(anyhow!("app '{}' not found: {}", name, status.message()));
+            }
+            Err(status) => return Err(status.into()),
+        };
+        if resp.app_id.is_empty() {
+            return Err(anyhow!("app '{}' not found", name));
+        }
+        Ok(resp.app_id)
+    }
+
+    /// Look up the App currently deployed under `name`, returning its id.
+    pub async fn by_deployment_name(&mut self, name: &str) -> Result<String> {
+        let req_msg = client::AppGetByDeploymentNameRequest {
+            name: name.to_string(),
+            environment_name: String::new(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self
+            .client
+            .stub
+            .app_get_by_deployment_name(req)
+            .await?
+            .into_inner();
+        if resp.app_id.is_empty() {
+            return Err(anyhow!("no app deployed as '{}'", name));
+        }
+        Ok(resp.app_id)
+    }
+
+    /// Stop an App and everything running in it.
+    pub async fn stop(&mut self, app_id: &str) -> Result<()> {
+        let req_msg = client::AppStopRequest {
+            app_id: app_id.to_string(),
+            source: client::AppStopSource::Unspecified as i32,
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.app_stop(req).await?;
+        Ok(())
+    }
+
+    /// The App's tags.
+    pub async fn tags(&mut self, app_id: &str) -> Result<HashMap<String, String>> {
+        let req_msg = client::AppGetTagsRequest {
+            app_id: app_id.to_string(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.app_get_tags(req).await?.into_inner();
+        Ok(resp.tags)
+    }
+
+    /// Replace the App's tags with `tags`. Tags not in `tags` are removed.
+    pub async fn set_tags(&mut self, app_id: &str, tags: HashMap<String, String>) -> Result<()> {
+        let req_msg = client::AppSetTagsRequest {
+            app_id: app_id.to_string(),
+            tags,
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.app_set_tags(req).await?;
+        Ok(())
+    }
+
+    /// Add `tags` to the App's tags, overwriting those with the same key and keeping the rest.
+    ///
+    /// This reads the current tags and then sets the merged map, so it is not atomic: tags set
+    /// by someone else in between are lost.
+    pub async fn merge_tags(&mut self, app_id: &str, tags: HashMap<String, String>) -> Result<()> {
+        let mut merged = self.tags(app_id).await?;
+        merged.extend(tags);
+        self.set_tags(app_id, merged).await
+    }
+
+    /// Deployments of the App, newest (highest version) first.
+    pub async fn deployment_history(&mut self, app_id: &str) -> Result<Vec<AppDeployment>> {
+        let req_msg = client::AppDeploymentHistoryRequest {
+            app_id: app_id.to_string(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self
+            .client
+            .stub
+            .app_deployment_history(req)
+            .await?
+            .into_inner();
+        let mut deployments: Vec<AppDeployment> = resp
+            .app_deployment_histories
+            .into_iter()
+            .map(|d| AppDeployment {
+                version: d.version,
+                client_version: d.client_version,
+                deployed_at: d.deployed_at,
+                deployed_by: d.deployed_by,
+                tag: d.tag,
+                rollback_version: d.rollback_version,
+                rollback_allowed: d.rollback_allowed,
+                commit: d.commit_info.map(|c| CommitInfo {
+                    vcs: c.vcs,
+                    branch: c.branch,
+                    commit_hash: c.commit_hash,
+                    commit_timestamp: c.commit_timestamp,
+                    dirty: c.dirty,
+                    author_name: c.author_name,
+                    author_email: c.author_email,
+                    repo_url: c.repo_url,
+                }),
+            })
+            .collect();
+        deployments.sort_by_key(|d| Reverse(d.version));
+        Ok(deployments)
+    }
+
+    /// Redeploy an earlier version of the App. A positive `version` names a deployment
+    /// version; a negative one goes back that many deployments, so `-1` is the previous one.
+    pub async fn rollback(&mut self, app_id: &str, version: i32) -> Result<()> {
+        if version == 0 {
+            return Err(anyhow!("rollback version must not be 0"));
+        }
+        let req_msg = client::AppRollbackRequest {
+            app_id: app_id.to_string(),
+            version,
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.app_rollback(req).await?;
+        Ok(())
+    }
+}
+
+impl From<client::AppState> for AppState {
+    fn from(state: client::AppState) -> Self {
+        match state {
+            client::AppState::Ephemeral => AppState::Ephemeral,
+            client::AppState::Detached => AppState::Detached,
+            client::AppState::Deployed => AppState::Deployed,
+            client::AppState::Stopping => AppState::Stopping,
+            client::AppState::Stopped => AppState::Stopped,
+            client::AppState::Initializing => AppState::Initializing,
+            client::AppState::Disabled => AppState::Disabled,
+            client::AppState::DetachedDisconnected => AppState::DetachedDisconnected,
+            _ => AppState::Unspecified,
+        }
+    }
+}
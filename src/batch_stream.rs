@@ -0,0 +1,122 @@
+use anyhow::Result;
+use futures::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::future::Future;
+
+/// A source of items fetched in batches, such as a server stream that is reopened from a
+/// cursor each time it closes.
+pub(crate) trait BatchSource: Send + 'static {
+    type Item: Send + 'static;
+
+    /// Fetch the next batch into `buffer`, which may stay empty if nothing arrived. Returns
+    /// `Ok(false)` once nothing more will arrive; items already buffered are still yielded.
+    fn next_batch(
+        &mut self,
+        buffer: &mut VecDeque<Self::Item>,
+    ) -> impl Future<Output = Result<bool>> + Send;
+}
+
+/// Yield the items of `source` one at a time, fetching a new batch whenever the buffer runs
+/// dry. The stream ends when the source is exhausted, or after yielding its first error.
+pub(crate) fn batch_stream<S: BatchSource>(source: S) -> impl Stream<Item = Result<S::Item>> {
+    let state = BatchState {
+        source,
+        buffer: VecDeque::new(),
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.buffer.pop_front() {
+                return Some((Ok(item), state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.source.next_batch(&mut state.buffer).await {
+                Ok(more) => state.done = !more,
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            }
+        }
+    })
+}
+
+struct BatchState<S: BatchSource> {
+    source: S,
+    buffer: VecDeque<S::Item>,
+    done: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use futures::{StreamExt, TryStreamExt};
+
+    /// Serves `batches` in order, then either ends or fails.
+    struct Batches {
+        batches: VecDeque<Vec<u32>>,
+        fail_at_end: bool,
+    }
+
+    impl BatchSource for Batches {
+        type Item = u32;
+
+        async fn next_batch(&mut self, buffer: &mut VecDeque<u32>) -> Result<bool> {
+            match self.batches.pop_front() {
+                Some(batch) => {
+                    buffer.extend(batch);
+                    Ok(true)
+                }
+                None if self.fail_at_end => Err(anyhow!("stream broke")),
+                None => Ok(false),
+            }
+        }
+    }
+
+    fn batches(batches: Vec<Vec<u32>>, fail_at_end: bool) -> Batches {
+        Batches {
+            batches: batches.into(),
+            fail_at_end,
+        }
+    }
+
+    #[tokio::test]
+    async fn yields_items_across_empty_batches() {
+        let source = batches(vec![vec![1, 2], vec![], vec![3]], false);
+        let items: Vec<u32> = batch_stream(source).try_collect().await.unwrap();
+        assert_eq!(items, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn yields_buffered_items_before_the_error_then_ends() {
+        let source = batches(vec![vec![1, 2]], true);
+        let results: Vec<_> = batch_stream(source).collect().await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert_eq!(results[1].as_ref().unwrap(), &2);
+        assert!(results[2].is_err());
+    }
+
+    /// Ends with a final batch, reporting exhaustion in the same call.
+    struct Final(bool);
+
+    impl BatchSource for Final {
+        type Item = &'static str;
+
+        async fn next_batch(&mut self, buffer: &mut VecDeque<&'static str>) -> Result<bool> {
+            assert!(!self.0, "polled after reporting exhaustion");
+            self.0 = true;
+            buffer.extend(["a", "b"]);
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn drains_the_last_batch_without_polling_again() {
+        let items: Vec<_> = batch_stream(Final(false)).try_collect().await.unwrap();
+        assert_eq!(items, vec!["a", "b"]);
+    }
+}
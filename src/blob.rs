@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use md5::Md5;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::Body;
+use sha2::{Digest, Sha256};
+use std::io::{Read, SeekFrom};
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::proto::modal::client::blob_create_response::UploadTypeOneof;
+
+/// Number of multipart upload parts sent concurrently.
+const PART_CONCURRENCY: usize = 4;
+
+/// Digests and length of a blob's content, as `BlobCreate` expects them.
+#[derive(Debug, PartialEq, Eq)]
+struct ContentHashes {
+    md5_base64: String,
+    sha256_base64: String,
+    len: u64,
+}
+
+impl ModalClient {
+    /// Upload the file at `path` to blob storage and return the blob id, for files too
+    /// large to send inline in a request. The file is streamed, never read into memory whole.
+    pub(crate) async fn blob_upload_file(&mut self, path: &Path) -> Result<String> {
+        let hashes = {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                let mut file = std::fs::File::open(&path)
+                    .map_err(|e| anyhow!("failed to open '{}': {}", path.display(), e))?;
+                content_hashes(&mut file)
+            })
+            .await??
+        };
+        let req_msg = client::BlobCreateRequest {
+            content_md5: hashes.md5_base64.clone(),
+            content_sha256_base64: hashes.sha256_base64,
+            content_length: hashes.len as i64,
+        };
+        let req = self.make_request(req_msg);
+        let resp = self.stub.blob_create(req).await?.into_inner();
+
+        match resp.upload_type_oneof {
+            Some(UploadTypeOneof::UploadUrl(url)) => {
+                put_range(
+                    &self.http,
+                    &url,
+                    path,
+                    (0, hashes.len),
+                    Some(&hashes.md5_base64),
+                )
+                .await?;
+            }
+            Some(UploadTypeOneof::Multipart(upload)) => {
+                self.multipart_upload(upload, path, hashes.len).await?;
+            }
+            None => return Err(anyhow!("no upload target returned for blob")),
+        }
+        Ok(resp.blob_id)
+    }
+
+    /// Upload the `len` bytes of the file at `path` in `part_length` parts, several at a time,
+    /// then complete the upload with the parts' ETags.
+    async fn multipart_upload(
+        &self,
+        upload: client::MultiPartUpload,
+        path: &Path,
+        len: u64,
+    ) -> Result<()> {
+        let part_length = u64::try_from(upload.part_length)
+            .ok()
+            .filter(|len| *len > 0)
+            .ok_or_else(|| anyhow!("invalid multipart part length {}", upload.part_length))?;
+        let ranges = part_ranges(len, part_length);
+        if ranges.len() != upload.upload_urls.len() {
+            return Err(anyhow!(
+                "multipart upload expects {} parts, got {} URLs",
+                ranges.len(),
+                upload.upload_urls.len()
+            ));
+        }
+
+        let etags: Vec<String> = stream::iter(upload.upload_urls.iter().zip(ranges).enumerate())
+            .map(|(i, (url, range))| async move {
+                let resp = put_range(&self.http, url, path, range, None).await?;
+                resp.headers()
+                    .get("ETag")
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("no ETag returned for part {}", i + 1))
+            })
+            .buffered(PART_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut completion = String::from("<CompleteMultipartUpload>\n");
+        for (i, etag) in etags.iter().enumerate() {
+            completion.push_str(&format!(
+                "<Part>\n<PartNumber>{}</PartNumber>\n<ETag>{}</ETag>\n</Part>\n",
+                i + 1,
+                etag
+            ));
+        }
+        completion.push_str("</CompleteMultipartUpload>");
+
+        self.http
+            .post(&upload.completion_url)
+            .body(completion)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// PUT the bytes `range` (offset, length) of the file at `path` to `url`, streaming them from
+/// disk. Whole-blob uploads pass `content_md5`; multipart parts do not.
+async fn put_range(
+    http: &reqwest::Client,
+    url: &str,
+    path: &Path,
+    (offset, len): (u64, u64),
+    content_md5: Option<&str>,
+) -> Result<reqwest::Response> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let body = Body::wrap_stream(ReaderStream::new(file.take(len)));
+    // Presigned URLs need the length up front; a streamed body would otherwise be chunked.
+    let mut req = http.put(url).header(CONTENT_LENGTH, len).body(body);
+    if let Some(md5) = content_md5 {
+        req = req
+            .header("Content-MD5", md5)
+            .header("Content-Type", "application/octet-stream");
+    }
+    Ok(req.send().await?.error_for_status()?)
+}
+
+/// MD5 and SHA-256 of everything read from `reader`, computed in one streaming pass.
+fn content_hashes<R: Read>(reader: &mut R) -> Result<ContentHashes> {
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut len = 0u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        md5.update(&buf[..n]);
+        sha256.update(&buf[..n]);
+        len += n as u64;
+    }
+    Ok(ContentHashes {
+        md5_base64: BASE64.encode(md5.finalize()),
+        sha256_base64: BASE64.encode(sha256.finalize()),
+        len,
+    })
+}
+
+/// (offset, length) of each `part_length` part of a `len`-byte blob. An empty blob is one
+/// empty part.
+fn part_ranges(len: u64, part_length: u64) -> Vec<(u64, u64)> {
+    let parts = len.div_ceil(part_length).max(1);
+    (0..parts)
+        .map(|i| {
+            let offset = i * part_length;
+            (offset, part_length.min(len - offset))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_content_in_one_pass() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let hashes = content_hashes(&mut &data[..]).unwrap();
+        assert_eq!(hashes.len, data.len() as u64);
+        assert_eq!(hashes.md5_base64, BASE64.encode(Md5::digest(&data)));
+        assert_eq!(hashes.sha256_base64, BASE64.encode(Sha256::digest(&data)));
+
+        let empty = content_hashes(&mut &b""[..]).unwrap();
+        assert_eq!(empty.md5_base64, "1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(empty.len, 0);
+    }
+
+    #[test]
+    fn splits_blobs_into_parts() {
+        assert_eq!(part_ranges(0, 10), vec![(0, 0)]);
+        assert_eq!(part_ranges(5, 10), vec![(0, 5)]);
+        assert_eq!(part_ranges(10, 10), vec![(0, 10)]);
+        assert_eq!(part_ranges(25, 10), vec![(0, 10), (10, 10), (20, 5)]);
+    }
+
+    #[tokio::test]
+    async fn puts_file_ranges_with_a_content_length() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = std::env::temp_dir().join(format!("modal-blob-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/part", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"3456") {
+                let n = conn.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed early");
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 200 OK\r\nETag: \"abc\"\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap().to_ascii_lowercase()
+        });
+
+        let resp = put_range(&reqwest::Client::new(), &url, &path, (3, 4), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["etag"], "\"abc\"");
+        let request = server.await.unwrap();
+        assert!(request.contains("content-length: 4\r\n"), "{}", request);
+        assert!(!request.contains("transfer-encoding"), "{}", request);
+        assert!(request.ends_with("\r\n\r\n3456"), "{}", request);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,22 @@
 use anyhow::{anyhow, Result};
-use bytes::Bytes;
 use reqwest::Client as HttpClient;
-use serde::{de::DeserializeOwned, Serialize};
 use std::time::Duration;
 use tokio::time::sleep;
 use tonic::metadata::MetadataValue;
 use tonic::transport::{Channel, Endpoint};
 use tonic::Request;
 
+use crate::proto::modal::client::generic_result::GenericStatus;
 use crate::proto::modal::client::modal_client_client::ModalClientClient;
 use crate::proto::modal::client::{
     DataFormat, FunctionGetOutputsRequest, FunctionGetRequest, FunctionInput, FunctionMapRequest,
-    FunctionPutInputsItem, FunctionPutInputsRequest,
+    FunctionPutInputsItem, FunctionPutInputsRequest, GenericResult,
 };
-use crate::serialization::{from_cbor, to_cbor};
+
+/// `result` if it reports a final status, i.e. the operation it describes has finished.
+pub(crate) fn final_result(result: Option<GenericResult>) -> Option<GenericResult> {
+    result.filter(|r| r.status() != GenericStatus::Unspecified)
+}
 
 /// The main client for interacting with Modal's API.
 ///
@@ -21,8 +24,7 @@ use crate::serialization::{from_cbor, to_cbor};
 #[derive(Clone)]
 pub struct ModalClient {
     pub stub: ModalClientClient<Channel>,
-    http: HttpClient,
-    max_inline: usize,
+    pub(crate) http: HttpClient,
     token_id: Option<String>,
     token_secret: Option<String>,
 }
@@ -127,7 +129,6 @@ impl ModalClient {
         Ok(Self {
             stub,
             http: HttpClient::new(),
-            max_inline: 16 * 1024 * 1024,
             token_id: token_id
                 .map(|s| s.to_string())
                 .or_else(|| std::env::var("MODAL_TOKEN_ID").ok()),
@@ -137,6 +138,19 @@ impl ModalClient {
         })
     }
 
+    /// A client for unit tests that never reaches a server: its channel connects lazily to
+    /// an unroutable address. Must be created inside a Tokio runtime.
+    #[cfg(test)]
+    pub(crate) fn for_tests() -> Self {
+        let channel = Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+        Self {
+            stub: ModalClientClient::new(channel),
+            http: HttpClient::new(),
+            token_id: None,
+            token_secret: None,
+        }
+    }
+
     pub(crate) fn make_request<T>(&self, msg: T) -> Request<T> {
         let mut req = Request::new(msg);
         // Standard metadata used by other SDKs
@@ -252,7 +266,7 @@ impl ModalClient {
             let resp = self.stub.function_get_outputs(get_req).await?.into_inner();
             if !resp.outputs.is_empty() {
                 let item = &resp.outputs[0];
-                if let Some(ref result) = item.result.as_ref() {
+                if let Some(result) = item.result.as_ref() {
                     match result.data_oneof {
                         Some(crate::proto::modal::client::function_result::DataOneof::Data(
                             ref data,
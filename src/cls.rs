@@ -4,16 +4,99 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::proto::modal::client;
-use crate::serialization::to_cbor;
+use crate::serialization::{from_cbor, to_cbor};
+
+/// Default number of bound function ids kept per `Cls`.
+const DEFAULT_BIND_CACHE_CAPACITY: usize = 1024;
 
 /// A referenced Modal class (service function) with metadata and helper methods.
+///
+/// Bound function ids returned by `FunctionBindParams` are cached per serialized parameter
+/// set, so repeated `instance` calls with the same parameters skip the RPC. Clones share the
+/// same cache until one of them is given its own with `with_bind_cache`.
 #[derive(Clone)]
 pub struct Cls {
     pub service_function_id: String,
     pub service_function_metadata: Option<client::FunctionHandleMetadata>,
     pub client: crate::client::ModalClient,
+    bind_cache: Arc<Mutex<BindCache>>,
+}
+
+/// Bounded cache from serialized `ClassParameterSet` bytes to `bound_function_id`.
+///
+/// When full, the least recently used entry is evicted. Entries older than `ttl` are treated
+/// as missing.
+struct BindCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<Vec<u8>, BindCacheEntry>,
+    /// Incremented on every access; orders entries by recency without relying on clock
+    /// resolution.
+    tick: u64,
+}
+
+struct BindCacheEntry {
+    bound_function_id: String,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+impl BindCache {
+    fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<String> {
+        let now = Instant::now();
+        let expired = match self.entries.get(key) {
+            Some(entry) => self
+                .ttl
+                .is_some_and(|ttl| now.duration_since(entry.inserted_at) >= ttl),
+            None => return None,
+        };
+        if expired {
+            self.entries.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.tick;
+        Some(entry.bound_function_id.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, bound_function_id: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                self.entries.remove(&k);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            BindCacheEntry {
+                bound_function_id,
+                inserted_at: Instant::now(),
+                last_used: self.tick,
+            },
+        );
+    }
 }
 
 /// An instantiated class with bound parameters. Methods map to function IDs.
@@ -43,11 +126,53 @@ impl crate::client::ModalClient {
             service_function_id: resp.function_id,
             service_function_metadata: resp.handle_metadata,
             client: self.clone(),
+            bind_cache: Arc::new(Mutex::new(BindCache::new(
+                DEFAULT_BIND_CACHE_CAPACITY,
+                None,
+            ))),
         })
     }
 }
 
 impl Cls {
+    /// Configure the bound function id cache. `capacity` bounds the number of parameter sets
+    /// kept (0 disables caching) and `ttl`, if set, expires entries after the given duration.
+    /// The returned `Cls` starts with its own empty cache; clones made before this call keep
+    /// sharing the previous one.
+    pub fn with_bind_cache(mut self, capacity: usize, ttl: Option<Duration>) -> Self {
+        self.bind_cache = Arc::new(Mutex::new(BindCache::new(capacity, ttl)));
+        self
+    }
+
+    /// Drop the cached bound function id for the given parameters, if any.
+    pub fn invalidate(&self, parameters: &HashMap<String, serde_cbor::Value>) -> Result<()> {
+        if let Some(schema) = self.parameter_schema() {
+            let serialized = encode_parameter_set(schema, parameters)?;
+            self.bind_cache.lock().unwrap().entries.remove(&serialized);
+        }
+        Ok(())
+    }
+
+    /// Drop all cached bound function ids.
+    pub fn clear_bind_cache(&self) {
+        self.bind_cache.lock().unwrap().entries.clear();
+    }
+
+    /// The parameter schema when the class uses proto parameter serialization.
+    fn parameter_schema(&self) -> Option<&[client::ClassParameterSpec]> {
+        let param_info = self
+            .service_function_metadata
+            .as_ref()?
+            .class_parameter_info
+            .as_ref()?;
+        // proto value 2 == PARAM_SERIALIZATION_FORMAT_PROTO
+        if param_info.format == 2 && !param_info.schema.is_empty() {
+            Some(&param_info.schema)
+        } else {
+            None
+        }
+    }
+
     /// Create an instance of the class, binding the given parameters.
     /// Parameters map should contain values matching the class parameter schema.
     pub async fn instance(
@@ -57,34 +182,38 @@ impl Cls {
         // If there is no parameter schema, the bound function id is the service function id.
         let mut function_id = self.service_function_id.clone();
 
-        if let Some(ref metadata) = self.service_function_metadata {
-            if let Some(ref param_info) = metadata.class_parameter_info.as_ref() {
-                // proto value 2 == PARAM_SERIALIZATION_FORMAT_PROTO
-                if param_info.format == 2 {
-                    let schema = &param_info.schema;
-                    if !schema.is_empty() {
-                        let serialized = encode_parameter_set(schema, &parameters)?;
-                        // Build bind params request
-                        let bind_req = client::FunctionBindParamsRequest {
-                            function_id: self.service_function_id.clone(),
-                            serialized_params: serialized,
-                            function_options: None,
-                            environment_name: String::new(),
-                            auth_secret: String::new(),
-                        };
-                        let req = self.client.make_request(bind_req);
-                        let resp = self
-                            .client
-                            .stub
-                            .function_bind_params(req)
-                            .await?
-                            .into_inner();
-                        if !resp.bound_function_id.is_empty() {
-                            function_id = resp.bound_function_id;
-                        }
-                    }
+        if let Some(schema) = self.parameter_schema() {
+            let serialized = encode_parameter_set(schema, &parameters)?;
+            let cached = self.bind_cache.lock().unwrap().get(&serialized);
+            if let Some(bound_function_id) = cached {
+                function_id = bound_function_id;
+            } else {
+                // Build bind params request
+                let bind_req = client::FunctionBindParamsRequest {
+                    function_id: self.service_function_id.clone(),
+                    serialized_params: serialized.clone(),
+                    function_options: None,
+                    environment_name: String::new(),
+                    auth_secret: String::new(),
+                };
+                let req = self.client.make_request(bind_req);
+                let resp = self
+                    .client
+                    .stub
+                    .function_bind_params(req)
+                    .await?
+                    .into_inner();
+                if !resp.bound_function_id.is_empty() {
+                    function_id = resp.bound_function_id;
+                    self.bind_cache
+                        .lock()
+                        .unwrap()
+                        .insert(serialized, function_id.clone());
                 }
             }
+        }
+
+        if let Some(ref metadata) = self.service_function_metadata {
             // Build method map from metadata.method_handle_metadata
             let mut methods = HashMap::new();
             for (name, _m) in metadata.method_handle_metadata.iter() {
@@ -117,13 +246,12 @@ impl ClsInstance {
             .clone();
         let cbor = to_cbor(args)?;
         let out = self.client.call_function_sync(&func_id, cbor).await?;
-        let decoded: R = serde_cbor::from_slice(&out)?;
-        Ok(decoded)
+        from_cbor(&out)
     }
 }
 
 fn encode_parameter_set(
-    schema: &Vec<client::ClassParameterSpec>,
+    schema: &[client::ClassParameterSpec],
     parameters: &HashMap<String, serde_cbor::Value>,
 ) -> Result<Vec<u8>> {
     let mut encoded: Vec<client::ClassParameterValue> = Vec::new();
@@ -163,7 +291,7 @@ fn encode_parameter_set(
             }
         } else if spec.has_default {
             // handle defaults where present by inspecting default_oneof
-            if let Some(ref d) = spec.default_oneof.as_ref() {
+            if let Some(d) = spec.default_oneof.as_ref() {
                 match d {
                     client::class_parameter_spec::DefaultOneof::StringDefault(s) => {
                         value.value_oneof = Some(
@@ -203,3 +331,59 @@ fn encode_parameter_set(
     set.encode(&mut buf)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind_cache_returns_inserted_ids() {
+        let mut cache = BindCache::new(4, None);
+        assert_eq!(cache.get(b"a"), None);
+        cache.insert(b"a".to_vec(), "fu-a".to_string());
+        assert_eq!(cache.get(b"a").as_deref(), Some("fu-a"));
+        cache.insert(b"a".to_vec(), "fu-a2".to_string());
+        assert_eq!(cache.get(b"a").as_deref(), Some("fu-a2"));
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn bind_cache_evicts_least_recently_used() {
+        let mut cache = BindCache::new(2, None);
+        cache.insert(b"a".to_vec(), "fu-a".to_string());
+        cache.insert(b"b".to_vec(), "fu-b".to_string());
+        // Touch "a" so "b" becomes the least recently used entry.
+        assert!(cache.get(b"a").is_some());
+        cache.insert(b"c".to_vec(), "fu-c".to_string());
+        assert_eq!(cache.get(b"b"), None);
+        assert_eq!(cache.get(b"a").as_deref(), Some("fu-a"));
+        assert_eq!(cache.get(b"c").as_deref(), Some("fu-c"));
+    }
+
+    #[test]
+    fn bind_cache_with_zero_capacity_stores_nothing() {
+        let mut cache = BindCache::new(0, None);
+        cache.insert(b"a".to_vec(), "fu-a".to_string());
+        assert_eq!(cache.get(b"a"), None);
+    }
+
+    #[test]
+    fn bind_cache_expires_entries_after_ttl() {
+        let mut cache = BindCache::new(4, Some(Duration::from_millis(20)));
+        cache.insert(b"a".to_vec(), "fu-a".to_string());
+        assert_eq!(cache.get(b"a").as_deref(), Some("fu-a"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(b"a"), None);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn bind_cache_ttl_counts_from_insertion_not_last_use() {
+        let mut cache = BindCache::new(4, Some(Duration::from_millis(40)));
+        cache.insert(b"a".to_vec(), "fu-a".to_string());
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(cache.get(b"a").is_some());
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(cache.get(b"a"), None);
+    }
+}
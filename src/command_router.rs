@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status};
+
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::retry::{is_transient, Backoff};
+
+/// A connection to the command router running next to a task, authenticated with the JWT
+/// returned by `TaskGetCommandRouterAccess`.
+///
+/// `S` is the generated gRPC stub for the router service being used.
+#[derive(Clone)]
+pub(crate) struct CommandRouter<S> {
+    client: ModalClient,
+    pub(crate) task_id: String,
+    stub: S,
+    auth: MetadataValue<Ascii>,
+    make_stub: fn(Channel) -> S,
+}
+
+impl<S: Clone> CommandRouter<S> {
+    /// Fetch router access for `task_id` and connect to it.
+    pub(crate) async fn connect(
+        client: &ModalClient,
+        task_id: &str,
+        make_stub: fn(Channel) -> S,
+    ) -> Result<Self> {
+        let mut client = client.clone();
+        let (stub, auth) = Self::open(&mut client, task_id, make_stub).await?;
+        Ok(Self {
+            client,
+            task_id: task_id.to_string(),
+            stub,
+            auth,
+            make_stub,
+        })
+    }
+
+    async fn open(
+        client: &mut ModalClient,
+        task_id: &str,
+        make_stub: fn(Channel) -> S,
+    ) -> Result<(S, MetadataValue<Ascii>)> {
+        let req_msg = client::TaskGetCommandRouterAccessRequest {
+            task_id: task_id.to_string(),
+        };
+        let req = client.make_request(req_msg);
+        let resp = client
+            .stub
+            .task_get_command_router_access(req)
+            .await?
+            .into_inner();
+        if resp.url.is_empty() {
+            return Err(anyhow!("no command router available for task {}", task_id));
+        }
+
+        let channel = Endpoint::from_shared(resp.url)?.connect().await?;
+        let auth = MetadataValue::try_from(format!("Bearer {}", resp.jwt))?;
+        Ok((make_stub(channel), auth))
+    }
+
+    /// Fetch a fresh JWT (and router URL) after the old one was rejected.
+    async fn refresh(&mut self) -> Result<()> {
+        let (stub, auth) = Self::open(&mut self.client, &self.task_id, self.make_stub).await?;
+        self.stub = stub;
+        self.auth = auth;
+        Ok(())
+    }
+
+    /// Run a router call, re-authenticating once if the JWT is rejected and retrying transient
+    /// failures with exponential backoff. `call` receives a stub and a helper that attaches the
+    /// router credentials to a request message.
+    pub(crate) async fn call<T, F, Fut>(&mut self, mut call: F) -> Result<T>
+    where
+        F: FnMut(S, Authorizer) -> Fut,
+        Fut: Future<Output = std::result::Result<T, Status>>,
+    {
+        let mut refreshed = false;
+        let mut backoff = Backoff::default();
+        loop {
+            let authorizer = Authorizer(self.auth.clone());
+            match call(self.stub.clone(), authorizer).await {
+                Ok(v) => return Ok(v),
+                Err(status) if status.code() == Code::Unauthenticated && !refreshed => {
+                    refreshed = true;
+                    self.refresh().await?;
+                }
+                Err(status) => backoff.retry(status).await?,
+            }
+        }
+    }
+}
+
+/// Whether `task_id` has a command router. Transient errors are returned so that a flaky
+/// control plane is not mistaken for a missing router.
+pub(crate) async fn router_available(client: &mut ModalClient, task_id: &str) -> Result<bool> {
+    let req_msg = client::TaskGetCommandRouterAccessRequest {
+        task_id: task_id.to_string(),
+    };
+    let req = client.make_request(req_msg);
+    match client.stub.task_get_command_router_access(req).await {
+        Ok(resp) => Ok(!resp.into_inner().url.is_empty()),
+        Err(status) if is_transient(&status) => Err(status.into()),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Attaches router credentials to request messages.
+pub(crate) struct Authorizer(MetadataValue<Ascii>);
+
+impl Authorizer {
+    pub(crate) fn request<T>(&self, msg: T) -> Request<T> {
+        let mut req = Request::new(msg);
+        req.metadata_mut().insert("authorization", self.0.clone());
+        req
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Result};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+use crate::pagination::list_all;
+use crate::proto::modal::client;
+use crate::serialization::{from_cbor, to_cbor};
+
+/// A handle to a named Modal Dict.
+///
+/// Keys and values are CBOR-encoded so they can be shared with Python `modal.Dict` clients
+/// that use the same encoding.
+pub struct ModalDict<K, V> {
+    pub dict_id: String,
+    client: crate::client::ModalClient,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Clone for ModalDict<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            dict_id: self.dict_id.clone(),
+            client: self.client.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl crate::client::ModalClient {
+    /// Look up a Dict by name, creating it if `create_if_missing` is set.
+    pub async fn dict_from_name<K, V>(
+        &mut self,
+        name: &str,
+        create_if_missing: bool,
+    ) -> Result<ModalDict<K, V>> {
+        let object_creation_type = if create_if_missing {
+            client::ObjectCreationType::CreateIfMissing
+        } else {
+            client::ObjectCreationType::Unspecified
+        };
+        let req_msg = client::DictGetOrCreateRequest {
+            deployment_name: name.to_string(),
+            environment_name: String::new(),
+            object_creation_type: object_creation_type as i32,
+            data: vec![],
+        };
+        let req = self.make_request(req_msg);
+        let resp = self.stub.dict_get_or_create(req).await?.into_inner();
+        if resp.dict_id.is_empty() {
+            return Err(anyhow!("dict not found"));
+        }
+
+        Ok(ModalDict {
+            dict_id: resp.dict_id,
+            client: self.clone(),
+            _types: PhantomData,
+        })
+    }
+
+    /// List the names of the Dicts in the default environment.
+    pub async fn dict_list(&mut self) -> Result<Vec<String>> {
+        let dicts = list_all(
+            |pagination| {
+                let mut client = self.clone();
+                async move {
+                    let req_msg = client::DictListRequest {
+                        environment_name: String::new(),
+                        pagination: Some(pagination),
+                    };
+                    let req = client.make_request(req_msg);
+                    let resp = client.stub.dict_list(req).await?.into_inner();
+                    Ok(resp.dicts)
+                }
+            },
+            |d| d.created_at,
+        )
+        .await?;
+        Ok(dicts.into_iter().map(|d| d.name).collect())
+    }
+}
+
+impl<K, V> ModalDict<K, V>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    /// Get the value for `key`, or `None` if it is not present.
+    pub async fn get(&mut self, key: &K) -> Result<Option<V>> {
+        let req_msg = client::DictGetRequest {
+            dict_id: self.dict_id.clone(),
+            key: to_cbor(key)?,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.dict_get(req).await?.into_inner();
+        match resp.value {
+            Some(ref value) if resp.found => Ok(Some(from_cbor(value)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Set `key` to `value`, overwriting any existing value.
+    pub async fn put(&mut self, key: &K, value: &V) -> Result<()> {
+        self.send_update(vec![encode_entry(key, value)?], false)
+            .await?;
+        Ok(())
+    }
+
+    /// Set `key` to `value` only if `key` is not already present.
+    /// Returns whether the entry was created.
+    pub async fn put_if_not_exists(&mut self, key: &K, value: &V) -> Result<bool> {
+        self.send_update(vec![encode_entry(key, value)?], true)
+            .await
+    }
+
+    /// Set several entries in a single request.
+    pub async fn update<'a, I>(&mut self, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a K, &'a V)>,
+        K: 'a,
+        V: 'a,
+    {
+        let updates = entries
+            .into_iter()
+            .map(|(k, v)| encode_entry(k, v))
+            .collect::<Result<Vec<_>>>()?;
+        if !updates.is_empty() {
+            self.send_update(updates, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Check whether `key` is present.
+    pub async fn contains(&mut self, key: &K) -> Result<bool> {
+        let req_msg = client::DictContainsRequest {
+            dict_id: self.dict_id.clone(),
+            key: to_cbor(key)?,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.dict_contains(req).await?.into_inner();
+        Ok(resp.found)
+    }
+
+    /// Remove `key` and return its value, or `None` if it was not present.
+    pub async fn pop(&mut self, key: &K) -> Result<Option<V>> {
+        let req_msg = client::DictPopRequest {
+            dict_id: self.dict_id.clone(),
+            key: to_cbor(key)?,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.dict_pop(req).await?.into_inner();
+        match resp.value {
+            Some(ref value) if resp.found => Ok(Some(from_cbor(value)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Number of entries in the Dict.
+    pub async fn len(&mut self) -> Result<usize> {
+        let req_msg = client::DictLenRequest {
+            dict_id: self.dict_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.dict_len(req).await?.into_inner();
+        Ok(resp.len.max(0) as usize)
+    }
+
+    /// Whether the Dict has no entries.
+    pub async fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Remove all entries.
+    pub async fn clear(&mut self) -> Result<()> {
+        let req_msg = client::DictClearRequest {
+            dict_id: self.dict_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.dict_clear(req).await?;
+        Ok(())
+    }
+
+    /// Stream all `(key, value)` pairs via `DictContents`.
+    pub async fn items(&mut self) -> Result<impl Stream<Item = Result<(K, V)>>> {
+        let entries = self.contents(true, true).await?;
+        Ok(entries.map(|entry| {
+            let entry = entry?;
+            Ok((from_cbor(&entry.key)?, from_cbor(&entry.value)?))
+        }))
+    }
+
+    /// Stream all keys via `DictContents`.
+    pub async fn keys(&mut self) -> Result<impl Stream<Item = Result<K>>> {
+        let entries = self.contents(true, false).await?;
+        Ok(entries.map(|entry| from_cbor(&entry?.key)))
+    }
+
+    /// Stream all values via `DictContents`.
+    pub async fn values(&mut self) -> Result<impl Stream<Item = Result<V>>> {
+        let entries = self.contents(false, true).await?;
+        Ok(entries.map(|entry| from_cbor(&entry?.value)))
+    }
+
+    /// Delete the Dict and all of its entries.
+    pub async fn delete(mut self) -> Result<()> {
+        let req_msg = client::DictDeleteRequest {
+            dict_id: self.dict_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.dict_delete(req).await?;
+        Ok(())
+    }
+
+    async fn send_update(
+        &mut self,
+        updates: Vec<client::DictEntry>,
+        if_not_exists: bool,
+    ) -> Result<bool> {
+        let req_msg = client::DictUpdateRequest {
+            dict_id: self.dict_id.clone(),
+            updates,
+            if_not_exists,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.dict_update(req).await?.into_inner();
+        Ok(resp.created)
+    }
+
+    async fn contents(
+        &mut self,
+        keys: bool,
+        values: bool,
+    ) -> Result<impl Stream<Item = Result<client::DictEntry>>> {
+        let req_msg = client::DictContentsRequest {
+            dict_id: self.dict_id.clone(),
+            keys,
+            values,
+        };
+        let req = self.client.make_request(req_msg);
+        let stream = self.client.stub.dict_contents(req).await?.into_inner();
+        Ok(stream.map(|entry| entry.map_err(anyhow::Error::from)))
+    }
+}
+
+fn encode_entry<K: Serialize, V: Serialize>(key: &K, value: &V) -> Result<client::DictEntry> {
+    Ok(client::DictEntry {
+        key: to_cbor(key)?,
+        value: to_cbor(value)?,
+    })
+}
@@ -0,0 +1,286 @@
+use anyhow::{anyhow, Result};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::codec::Streaming;
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::client::{final_result, ModalClient};
+use crate::proto::modal::client;
+use crate::proto::modal::client::generic_result::GenericStatus;
+
+/// Environment variable overriding the image builder version, as in Modal's other clients.
+const BUILDER_VERSION_ENV: &str = "MODAL_IMAGE_BUILDER_VERSION";
+
+/// Server-side wait used by each `ImageJoinStreaming` request before it is reopened.
+const JOIN_POLL_SECS: f32 = 55.0;
+
+/// Definition of a container image: a registry base image plus Dockerfile commands, built
+/// by Modal with `ImageSpec::build`.
+#[derive(Clone, Debug)]
+pub struct ImageSpec {
+    image: client::Image,
+    force_build: bool,
+    ignore_cache: bool,
+    builder_version: Option<String>,
+}
+
+/// How the registry of a private base image is authenticated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistryAuth {
+    /// AWS ECR, with credentials from a Secret.
+    Aws,
+    /// Google Artifact Registry, with a service account from a Secret.
+    Gcp,
+    /// Username and password (`REGISTRY_USERNAME`, `REGISTRY_PASSWORD`) from a Secret.
+    StaticCreds,
+}
+
+/// A running image build, returned by `ImageSpec::build`. Streams the build's log output
+/// and ends when the image is ready, or yields an error if the build failed.
+pub struct ImageBuild {
+    pub image_id: String,
+    logs: BoxStream<'static, Result<String>>,
+}
+
+impl ImageSpec {
+    /// Start from the image `tag` in a container registry, e.g. `"python:3.12-slim"`.
+    pub fn from_registry(tag: &str) -> Self {
+        ImageSpec {
+            image: client::Image {
+                dockerfile_commands: vec![format!("FROM {}", tag)],
+                ..Default::default()
+            },
+            force_build: false,
+            ignore_cache: false,
+            builder_version: None,
+        }
+    }
+
+    /// Pull the base image from a private registry, authenticating with the Secret
+    /// `secret_id`.
+    pub fn registry_auth(mut self, auth: RegistryAuth, secret_id: &str) -> Self {
+        let auth_type = match auth {
+            RegistryAuth::Aws => client::RegistryAuthType::Aws,
+            RegistryAuth::Gcp => client::RegistryAuthType::Gcp,
+            RegistryAuth::StaticCreds => client::RegistryAuthType::StaticCreds,
+        };
+        self.image.image_registry_config = Some(client::ImageRegistryConfig {
+            registry_auth_type: auth_type as i32,
+            secret_id: secret_id.to_string(),
+        });
+        self
+    }
+
+    /// Append Dockerfile commands, e.g. `"RUN pip install numpy"`.
+    pub fn dockerfile_commands<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.image
+            .dockerfile_commands
+            .extend(commands.into_iter().map(Into::into));
+        self
+    }
+
+    /// Make a file available to `COPY` commands under `filename`.
+    pub fn context_file(mut self, filename: &str, data: Vec<u8>) -> Self {
+        self.image.context_files.push(client::ImageContextFile {
+            filename: filename.to_string(),
+            data,
+        });
+        self
+    }
+
+    /// Expose a Secret's environment variables to `RUN` commands, by id.
+    pub fn secret(mut self, secret_id: &str) -> Self {
+        self.image.secret_ids.push(secret_id.to_string());
+        self
+    }
+
+    /// Set a `--build-arg` for `ARG` substitution.
+    pub fn build_arg(mut self, name: &str, value: &str) -> Self {
+        self.image
+            .build_args
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Rebuild even if an identical image exists, replacing it in the cache.
+    pub fn force_build(mut self, enabled: bool) -> Self {
+        self.force_build = enabled;
+        self
+    }
+
+    /// Rebuild even if an identical image exists, without replacing it in the cache.
+    pub fn ignore_cache(mut self, enabled: bool) -> Self {
+        self.ignore_cache = enabled;
+        self
+    }
+
+    /// Image builder version to build with. Defaults to `MODAL_IMAGE_BUILDER_VERSION` if
+    /// set, else the version configured for the environment.
+    pub fn builder_version(mut self, version: &str) -> Self {
+        self.builder_version = Some(version.to_string());
+        self
+    }
+
+    /// Build the image in the App `app_id`, or reuse an identical one already built.
+    pub async fn build(&self, client: &mut ModalClient, app_id: &str) -> Result<ImageBuild> {
+        let builder_version = match &self.builder_version {
+            Some(version) => version.clone(),
+            None => client.image_builder_version().await?,
+        };
+        let req_msg = client::ImageGetOrCreateRequest {
+            image: Some(self.image.clone()),
+            app_id: app_id.to_string(),
+            force_build: self.force_build,
+            ignore_cache: self.ignore_cache,
+            builder_version,
+            ..Default::default()
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.image_get_or_create(req).await?.into_inner();
+
+        let state = JoinState {
+            client: client.clone(),
+            image_id: resp.image_id.clone(),
+            last_entry_id: String::new(),
+            stream: None,
+            result: final_result(resp.result),
+        };
+        Ok(ImageBuild {
+            image_id: resp.image_id,
+            logs: batch_stream(state).boxed(),
+        })
+    }
+}
+
+impl ImageBuild {
+    /// Wait for the build to finish, discarding its logs, and return the image id.
+    pub async fn wait(mut self) -> Result<String> {
+        while let Some(line) = self.logs.next().await {
+            line?;
+        }
+        Ok(self.image_id)
+    }
+}
+
+impl Stream for ImageBuild {
+    type Item = Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.logs.as_mut().poll_next(cx)
+    }
+}
+
+/// Progress through `ImageJoinStreaming`, resumed from the last entry id after reconnects.
+struct JoinState {
+    client: ModalClient,
+    image_id: String,
+    last_entry_id: String,
+    stream: Option<Streaming<client::ImageJoinStreamingResponse>>,
+    /// The build result, reported once the logs received with it have been yielded.
+    result: Option<client::GenericResult>,
+}
+
+impl BatchSource for JoinState {
+    type Item = String;
+
+    async fn next_batch(&mut self, logs: &mut VecDeque<String>) -> Result<bool> {
+        match self.result.take() {
+            Some(result) if result.status() == GenericStatus::Success => return Ok(false),
+            Some(result) => {
+                return Err(anyhow!(
+                    "image build failed: {:?} {}",
+                    result.status(),
+                    result.exception
+                ))
+            }
+            None => {}
+        }
+        if self.stream.is_none() {
+            let req_msg = client::ImageJoinStreamingRequest {
+                image_id: self.image_id.clone(),
+                timeout: JOIN_POLL_SECS,
+                last_entry_id: self.last_entry_id.clone(),
+                include_logs_for_finished: true,
+            };
+            let req = self.client.make_request(req_msg);
+            self.stream = Some(
+                self.client
+                    .stub
+                    .image_join_streaming(req)
+                    .await?
+                    .into_inner(),
+            );
+        }
+        let stream = self.stream.as_mut().expect("stream was just opened");
+        let Some(resp) = stream.message().await? else {
+            self.stream = None;
+            return Ok(true);
+        };
+        if !resp.entry_id.is_empty() {
+            self.last_entry_id = resp.entry_id;
+        }
+        logs.extend(
+            resp.task_logs
+                .into_iter()
+                .map(|log| log.data)
+                .filter(|data| !data.is_empty()),
+        );
+        self.result = final_result(resp.result);
+        if resp.eof {
+            self.stream = None;
+        }
+        Ok(true)
+    }
+}
+
+impl ModalClient {
+    /// Image builder version to build with when an `ImageSpec` does not set one:
+    /// `MODAL_IMAGE_BUILDER_VERSION` if set, else the default environment's setting.
+    async fn image_builder_version(&mut self) -> Result<String> {
+        if let Ok(version) = std::env::var(BUILDER_VERSION_ENV) {
+            return Ok(version);
+        }
+        let req_msg = client::EnvironmentGetOrCreateRequest {
+            deployment_name: String::new(),
+            object_creation_type: client::ObjectCreationType::Unspecified as i32,
+        };
+        let req = self.make_request(req_msg);
+        let resp = self.stub.environment_get_or_create(req).await?.into_inner();
+        Ok(resp
+            .metadata
+            .and_then(|m| m.settings)
+            .map(|s| s.image_builder_version)
+            .unwrap_or_default())
+    }
+
+    /// Check that the image `image_id` exists and return its id.
+    pub async fn image_from_id(&mut self, image_id: &str) -> Result<String> {
+        let req_msg = client::ImageFromIdRequest {
+            image_id: image_id.to_string(),
+        };
+        let req = self.make_request(req_msg);
+        match self.stub.image_from_id(req).await {
+            Ok(resp) => Ok(resp.into_inner().image_id),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                Err(anyhow!("image '{}' not found", image_id))
+            }
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Delete the image `image_id`.
+    pub async fn image_delete(&mut self, image_id: &str) -> Result<()> {
+        let req_msg = client::ImageDeleteRequest {
+            image_id: image_id.to_string(),
+        };
+        let req = self.make_request(req_msg);
+        self.stub.image_delete(req).await?;
+        Ok(())
+    }
+}
@@ -45,7 +45,7 @@
 //! # Examples
 //!
 //! ```no_run
-//! use modal_rust::ModalClient;
+//! use modal::ModalClient;
 //! use serde::{Serialize, Deserialize};
 //! use anyhow::Result;
 //!
@@ -61,20 +61,70 @@
 //!     let function_id = client.function_get("my-app", "echo").await?;
 //!     let args = EchoArgs { msg: "hello".to_string() };
 //!     
-//!     let result: EchoArgs = client.call_function(&function_id, &args).await?;
+//!     let out = client.call_function_sync(&function_id, serde_cbor::to_vec(&args)?).await?;
+//!     let result: EchoArgs = serde_cbor::from_slice(&out)?;
 //!     println!("Echo response: {}", result.msg);
 //!     Ok(())
 //! }
 //! ```
 
+mod app;
+mod batch_stream;
+mod blob;
 mod client;
 mod cls;
+mod command_router;
+mod dict;
+mod image;
+mod local_fs;
+mod mount;
+mod pagination;
 mod proto;
+mod queue;
+mod retry;
+mod sandbox;
+mod sandbox_exec;
+mod sandbox_fs;
+mod sandbox_http;
+mod sandbox_io;
+mod sandbox_list;
+mod sandbox_pool;
+mod sandbox_pty;
+mod sandbox_resources;
+mod sandbox_snapshot;
+mod sandbox_tunnel;
+mod sandbox_usage;
+mod secret;
 mod serialization;
+mod task_exec;
+mod volume;
+mod volume_file;
+mod volume_sync;
 
 // Re-export the main types
+pub use app::{AppDeployment, AppListItem, AppState, Apps, CommitInfo};
 pub use client::ModalClient;
 pub use cls::{Cls, ClsInstance};
+pub use dict::ModalDict;
+pub use image::{ImageBuild, ImageSpec, RegistryAuth};
+pub use mount::Mount;
+pub use queue::ModalQueue;
+pub use sandbox::{Sandbox, SandboxBuilder, SandboxExitStatus, SandboxStatus};
+pub use sandbox_exec::{ExecExitStatus, ExecOptions, Process, ProcessOutput, ProcessStdin};
+pub use sandbox_fs::{FileWatchEvent, FileWatchEventKind, SandboxFs};
+pub use sandbox_http::{ConnectToken, SandboxHttpClient};
+pub use sandbox_io::{SandboxOutput, SandboxStdin};
+pub use sandbox_list::{SandboxFilter, SandboxInfo, Sandboxes, TerminateReport};
+pub use sandbox_pool::{PoolMetrics, SandboxLease, SandboxPool, SandboxPoolOptions};
+pub use sandbox_pty::{PtyControl, PtySession, PtySize};
+pub use sandbox_resources::GpuSpec;
+pub use sandbox_snapshot::RestoreName;
+pub use sandbox_tunnel::{PortForward, Tunnel, TunnelType};
+pub use sandbox_usage::{ResourceUsage, UsageDelta, UsageMeter};
+pub use secret::{SecretInfo, Secrets};
+pub use volume::{Volume, VolumeEntry, VolumeEntryType, VolumeInfo};
+pub use volume_file::VolumeFile;
+pub use volume_sync::{SyncOptions, SyncReport};
 
 // Convenience type alias
 pub type Error = anyhow::Error;
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// SHA-256 digests of consecutive `block_size` blocks read from `reader`. The last block may
+/// be shorter; an empty reader has no blocks.
+pub(crate) fn hash_blocks<R: Read>(reader: &mut R, block_size: u64) -> Result<Vec<[u8; 32]>> {
+    let mut hashes = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let mut hasher = Sha256::new();
+        let mut block_len = 0u64;
+        while block_len < block_size {
+            let want = buf.len().min((block_size - block_len) as usize);
+            let n = reader.read(&mut buf[..want])?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            block_len += n as u64;
+        }
+        if block_len == 0 {
+            return Ok(hashes);
+        }
+        hashes.push(hasher.finalize().into());
+        if block_len < block_size {
+            return Ok(hashes);
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// All regular files below `dir`, following no symlinks.
+pub(crate) fn walk_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `path` relative to `base`, joined with `/` regardless of platform.
+pub(crate) fn relative_slash_path(base: &Path, path: &Path) -> Result<String> {
+    Ok(path
+        .strip_prefix(base)?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Join a remote prefix and a relative path with a single `/`.
+pub(crate) fn join_remote(prefix: &str, rel: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let rel = rel.trim_start_matches('/');
+    if prefix.is_empty() {
+        rel.to_string()
+    } else {
+        format!("{}/{}", prefix, rel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn hash_blocks_splits_on_block_boundaries() {
+        let cases: &[(&[u8], Vec<&[u8]>)] = &[
+            (b"", vec![]),
+            (b"abc", vec![b"abc"]),
+            (b"abcd", vec![b"abcd"]),
+            (b"abcde", vec![b"abcd", b"e"]),
+            (b"abcdefgh", vec![b"abcd", b"efgh"]),
+        ];
+        for (input, blocks) in cases {
+            let expected: Vec<[u8; 32]> = blocks.iter().map(|b| sha256(b)).collect();
+            assert_eq!(
+                hash_blocks(&mut &input[..], 4).unwrap(),
+                expected,
+                "input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn hash_blocks_handles_blocks_larger_than_read_buffer() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let hashes = hash_blocks(&mut &data[..], 100_000).unwrap();
+        assert_eq!(
+            hashes,
+            vec![sha256(&data[..100_000]), sha256(&data[100_000..])]
+        );
+    }
+
+    #[test]
+    fn relative_slash_path_joins_components_with_slash() {
+        let base = Path::new("/data/project");
+        let path = base.join("src").join("lib.rs");
+        assert_eq!(relative_slash_path(base, &path).unwrap(), "src/lib.rs");
+        assert!(relative_slash_path(base, Path::new("/elsewhere/file")).is_err());
+    }
+
+    #[test]
+    fn join_remote_uses_a_single_slash() {
+        let cases = [
+            ("", "a/b", "a/b"),
+            ("/", "a", "a"),
+            ("/root", "a", "/root/a"),
+            ("/root/", "/a", "/root/a"),
+            ("root", "a/b", "root/a/b"),
+        ];
+        for (prefix, rel, expected) in cases {
+            assert_eq!(
+                join_remote(prefix, rel),
+                expected,
+                "{:?} + {:?}",
+                prefix,
+                rel
+            );
+        }
+    }
+}
@@ -1,10 +1,5 @@
-mod client;
-mod proto;
-mod serialization;
-
-use crate::client::ModalClient;
-use crate::serialization::to_cbor;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use modal::ModalClient;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -28,6 +23,15 @@ async fn main() -> Result<()> {
     )
     .await?;
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("shell") {
+        let sandbox_id = args
+            .get(1)
+            .ok_or_else(|| anyhow!("usage: modal-rust shell <sandbox-id>"))?;
+        let code = shell::run(&client, sandbox_id).await?;
+        std::process::exit(code);
+    }
+
     // Replace these with your deployed app and function names
     let app_name = std::env::var("MODAL_APP").unwrap_or_else(|_| "my-app".to_string());
     let function_name = std::env::var("MODAL_FUNCTION").unwrap_or_else(|_| "function".to_string());
@@ -39,7 +43,7 @@ async fn main() -> Result<()> {
     let args = EchoArgs {
         msg: "hello from rust".to_string(),
     };
-    let cbor = to_cbor(&args)?;
+    let cbor = serde_cbor::to_vec(&args)?;
     let out_bytes = client.call_function_sync(&function_id, cbor).await?;
 
     // Try to decode returned CBOR into a serde_json::Value for demo
@@ -48,3 +52,144 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// `shell <sandbox-id>`: an interactive shell in a running Sandbox.
+#[cfg(unix)]
+mod shell {
+    use anyhow::{anyhow, Result};
+    use futures::StreamExt;
+    use modal::{ExecExitStatus, ModalClient, PtySize, Sandbox};
+    use std::mem::MaybeUninit;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::signal::unix::{signal, SignalKind};
+
+    /// Attach the local terminal to a shell in the Sandbox and return the shell's exit code.
+    pub async fn run(client: &ModalClient, sandbox_id: &str) -> Result<i32> {
+        // Fail before starting a remote shell that could never be driven interactively.
+        let raw = RawMode::enable()?;
+        let mut sandbox = Sandbox::from_id(client, sandbox_id);
+        let session = sandbox.attach_pty(terminal_size()).await?;
+        let mut control = session.control();
+        let mut stdin = session.stdin;
+        let mut output = session.output;
+
+        let input = tokio::spawn(async move {
+            let mut local = tokio::io::stdin();
+            let mut buf = [0u8; 4096];
+            loop {
+                match local.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if stdin.write(&buf[..n]).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            let _ = stdin.close().await;
+        });
+
+        let mut resizer = control.clone();
+        let resize = tokio::spawn(async move {
+            let Ok(mut winch) = signal(SignalKind::window_change()) else {
+                return;
+            };
+            while winch.recv().await.is_some() {
+                let _ = resizer.resize(terminal_size()).await;
+            }
+        });
+
+        let mut local = tokio::io::stdout();
+        let mut result = Ok(());
+        while let Some(chunk) = output.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    local.write_all(&chunk).await?;
+                    local.flush().await?;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        input.abort();
+        resize.abort();
+        drop(raw);
+        result?;
+
+        // Report a signal the way a local shell does, as 128 plus the signal number.
+        Ok(match control.wait().await? {
+            ExecExitStatus::Code(code) => code,
+            ExecExitStatus::Signal(number) => 128 + number,
+        })
+    }
+
+    /// Size of the local terminal, or 80x24 if stdout is not a terminal.
+    fn terminal_size() -> PtySize {
+        let mut ws = MaybeUninit::<libc::winsize>::zeroed();
+        // SAFETY: TIOCGWINSZ writes a `winsize` into the pointer on success.
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, ws.as_mut_ptr()) };
+        if ok != 0 {
+            return PtySize::default();
+        }
+        // SAFETY: the ioctl succeeded, so `ws` is initialized.
+        let ws = unsafe { ws.assume_init() };
+        if ws.ws_row == 0 || ws.ws_col == 0 {
+            return PtySize::default();
+        }
+        PtySize {
+            rows: ws.ws_row,
+            cols: ws.ws_col,
+        }
+    }
+
+    /// Puts stdin's terminal into raw mode, restoring the previous mode when dropped.
+    struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        fn enable() -> Result<RawMode> {
+            // SAFETY: isatty only inspects the file descriptor.
+            if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+                return Err(anyhow!("the shell command requires stdin to be a terminal"));
+            }
+            let mut termios = MaybeUninit::<libc::termios>::zeroed();
+            // SAFETY: tcgetattr fills in `termios` on success.
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            // SAFETY: tcgetattr succeeded, so `termios` is initialized.
+            let original = unsafe { termios.assume_init() };
+            let mut raw = original;
+            // SAFETY: `raw` is a valid termios and stdin is a terminal.
+            unsafe {
+                libc::cfmakeraw(&mut raw);
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+            }
+            Ok(RawMode { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the attributes read in `enable`.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod shell {
+    use anyhow::{anyhow, Result};
+    use modal::ModalClient;
+
+    pub async fn run(_client: &ModalClient, _sandbox_id: &str) -> Result<i32> {
+        Err(anyhow!("the shell command requires a Unix terminal"))
+    }
+}
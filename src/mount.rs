@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::client::ModalClient;
+use crate::local_fs::{file_mode, join_remote, relative_slash_path, walk_files};
+use crate::proto::modal::client;
+use crate::proto::modal::client::mount_put_file_request::DataOneof;
+
+/// Files at least this large are uploaded as blobs instead of inline.
+const LARGE_FILE_LIMIT: u64 = 4 * 1024 * 1024;
+
+/// Number of files hashed and uploaded concurrently.
+const UPLOAD_CONCURRENCY: usize = 8;
+
+/// A set of files uploaded from the local machine, attachable to a Sandbox with
+/// `SandboxBuilder::mount`.
+#[derive(Clone, Debug)]
+pub struct Mount {
+    pub mount_id: String,
+}
+
+/// A local file to be mounted at `remote_path`.
+struct LocalFile {
+    local_path: PathBuf,
+    remote_path: String,
+    sha256_hex: String,
+    size: u64,
+    mode: Option<u32>,
+}
+
+impl Mount {
+    /// Upload the files below `local_dir` for which `filter` returns true, given their path
+    /// relative to `local_dir`, to appear under the absolute path `remote_prefix`. The Mount
+    /// belongs to the App `app_id`.
+    ///
+    /// Files are identified by their SHA-256, so content the server already holds is not
+    /// uploaded again.
+    pub async fn from_local_dir<F>(
+        client: &mut ModalClient,
+        app_id: &str,
+        local_dir: impl AsRef<Path>,
+        remote_prefix: &str,
+        filter: F,
+    ) -> Result<Mount>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        if !remote_prefix.starts_with('/') {
+            return Err(anyhow!("mount path '{}' must be absolute", remote_prefix));
+        }
+        let local_dir = local_dir.as_ref().to_path_buf();
+        let walk_dir = local_dir.clone();
+        let paths = tokio::task::spawn_blocking(move || walk_files(&walk_dir)).await??;
+        let mut selected = Vec::new();
+        for path in paths {
+            let rel = path.strip_prefix(&local_dir)?;
+            if filter(rel) {
+                let remote_path =
+                    join_remote(remote_prefix, &relative_slash_path(&local_dir, &path)?);
+                selected.push((path, remote_path));
+            }
+        }
+
+        let files: Vec<LocalFile> = stream::iter(selected)
+            .map(|(local_path, remote_path)| async move {
+                tokio::task::spawn_blocking(move || hash_file(local_path, remote_path)).await?
+            })
+            .buffered(UPLOAD_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        stream::iter(&files)
+            .map(|file| {
+                let mut client = client.clone();
+                async move { put_file(&mut client, file).await }
+            })
+            .buffer_unordered(UPLOAD_CONCURRENCY)
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        let req_msg = client::MountGetOrCreateRequest {
+            object_creation_type: client::ObjectCreationType::AnonymousOwnedByApp as i32,
+            files: files
+                .iter()
+                .map(|file| client::MountFile {
+                    filename: file.remote_path.clone(),
+                    sha256_hex: file.sha256_hex.clone(),
+                    size: Some(file.size),
+                    mode: file.mode,
+                })
+                .collect(),
+            app_id: app_id.to_string(),
+            ..Default::default()
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.mount_get_or_create(req).await?.into_inner();
+        Ok(Mount {
+            mount_id: resp.mount_id,
+        })
+    }
+}
+
+fn hash_file(local_path: PathBuf, remote_path: String) -> Result<LocalFile> {
+    let mut file = std::fs::File::open(&local_path)
+        .map_err(|e| anyhow!("failed to open '{}': {}", local_path.display(), e))?;
+    let metadata = file.metadata()?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let sha256_hex = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(LocalFile {
+        local_path,
+        remote_path,
+        sha256_hex,
+        size: metadata.len(),
+        mode: file_mode(&metadata),
+    })
+}
+
+/// Upload a file's content unless the server already has it.
+async fn put_file(client: &mut ModalClient, file: &LocalFile) -> Result<()> {
+    let req_msg = client::MountPutFileRequest {
+        sha256_hex: file.sha256_hex.clone(),
+        data_oneof: None,
+    };
+    let req = client.make_request(req_msg);
+    if client.stub.mount_put_file(req).await?.into_inner().exists {
+        return Ok(());
+    }
+
+    let data_oneof = if file.size >= LARGE_FILE_LIMIT {
+        DataOneof::DataBlobId(client.blob_upload_file(&file.local_path).await?)
+    } else {
+        DataOneof::Data(tokio::fs::read(&file.local_path).await?)
+    };
+    let req_msg = client::MountPutFileRequest {
+        sha256_hex: file.sha256_hex.clone(),
+        data_oneof: Some(data_oneof),
+    };
+    let req = client.make_request(req_msg);
+    client.stub.mount_put_file(req).await?;
+    Ok(())
+}
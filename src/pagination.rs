@@ -0,0 +1,81 @@
+use anyhow::Result;
+use std::future::Future;
+
+use crate::proto::modal::client;
+
+/// Number of objects requested per page from RPCs that take a `ListPagination`.
+pub(crate) const LIST_PAGE_SIZE: i32 = 100;
+
+/// Fetch every page of a list RPC paged by `ListPagination`.
+///
+/// `fetch` is called with the pagination for each page and returns its items, newest first.
+/// The creation time of the last item, given by `created_at`, is the cursor for the next page.
+pub(crate) async fn list_all<T, F, Fut>(mut fetch: F, created_at: fn(&T) -> f64) -> Result<Vec<T>>
+where
+    F: FnMut(client::ListPagination) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let mut items = Vec::new();
+    let mut created_before = 0.0;
+    loop {
+        let page = fetch(client::ListPagination {
+            max_objects: LIST_PAGE_SIZE,
+            created_before,
+        })
+        .await?;
+        let full = page.len() >= LIST_PAGE_SIZE as usize;
+        let cursor = page.last().map(created_at);
+        items.extend(page);
+        match cursor {
+            // Stop if the cursor does not move back, rather than fetching the same page forever.
+            Some(t) if full && (created_before == 0.0 || t < created_before) => created_before = t,
+            _ => return Ok(items),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[tokio::test]
+    async fn list_all_follows_cursor_until_short_page() {
+        // 250 items created at t=250..1, served newest first.
+        let all: Vec<f64> = (1..=250).rev().map(f64::from).collect();
+        let requests = RefCell::new(Vec::new());
+        let items = list_all(
+            |p: client::ListPagination| {
+                requests.borrow_mut().push(p.created_before);
+                let page: Vec<f64> = all
+                    .iter()
+                    .copied()
+                    .filter(|t| p.created_before == 0.0 || *t < p.created_before)
+                    .take(p.max_objects as usize)
+                    .collect();
+                async move { Ok(page) }
+            },
+            |t| *t,
+        )
+        .await
+        .unwrap();
+        assert_eq!(items, all);
+        assert_eq!(*requests.borrow(), vec![0.0, 151.0, 51.0]);
+    }
+
+    #[tokio::test]
+    async fn list_all_stops_when_cursor_does_not_advance() {
+        let mut calls = 0;
+        let items = list_all(
+            |_| {
+                calls += 1;
+                async { Ok(vec![5.0; LIST_PAGE_SIZE as usize]) }
+            },
+            |t: &f64| *t,
+        )
+        .await
+        .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(items.len(), 2 * LIST_PAGE_SIZE as usize);
+    }
+}
@@ -1,7 +1,15 @@
 // Generated prost/tonic types will be included from OUT_DIR by build.rs
-// This module provides the generated `modal::client` types.
+// This module provides the generated `modal::client`, `modal::sandbox_router` and
+// `modal::task_command_router` types.
+#[allow(dead_code, clippy::all)]
 pub mod modal {
     pub mod client {
         include!(concat!(env!("OUT_DIR"), "/modal.client.rs"));
     }
+    pub mod sandbox_router {
+        include!(concat!(env!("OUT_DIR"), "/modal.sandbox_router.rs"));
+    }
+    pub mod task_command_router {
+        include!(concat!(env!("OUT_DIR"), "/modal.task_command_router.rs"));
+    }
 }
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::proto::modal::client;
+use crate::serialization::{from_cbor, to_cbor};
+
+/// Partition TTL used by the Python client when none is given (24 hours).
+const DEFAULT_PARTITION_TTL_SECONDS: i32 = 24 * 3600;
+
+/// Longest single `QueueGet` wait; longer timeouts are split into several requests.
+const MAX_GET_POLL: Duration = Duration::from_secs(50);
+
+/// A handle to a named Modal Queue.
+///
+/// Values are CBOR-encoded so they can be exchanged with Python `modal.Queue` clients that use
+/// the same encoding. A handle targets the default partition unless scoped with `partition`.
+pub struct ModalQueue<T> {
+    pub queue_id: String,
+    client: crate::client::ModalClient,
+    partition_key: Vec<u8>,
+    partition_ttl_seconds: i32,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for ModalQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue_id: self.queue_id.clone(),
+            client: self.client.clone(),
+            partition_key: self.partition_key.clone(),
+            partition_ttl_seconds: self.partition_ttl_seconds,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl crate::client::ModalClient {
+    /// Look up a Queue by name, creating it if `create_if_missing` is set.
+    pub async fn queue_from_name<T>(
+        &mut self,
+        name: &str,
+        create_if_missing: bool,
+    ) -> Result<ModalQueue<T>> {
+        let object_creation_type = if create_if_missing {
+            client::ObjectCreationType::CreateIfMissing
+        } else {
+            client::ObjectCreationType::Unspecified
+        };
+        let req_msg = client::QueueGetOrCreateRequest {
+            deployment_name: name.to_string(),
+            environment_name: String::new(),
+            object_creation_type: object_creation_type as i32,
+        };
+        let req = self.make_request(req_msg);
+        let resp = self.stub.queue_get_or_create(req).await?.into_inner();
+        if resp.queue_id.is_empty() {
+            return Err(anyhow!("queue not found"));
+        }
+
+        Ok(ModalQueue {
+            queue_id: resp.queue_id,
+            client: self.clone(),
+            partition_key: Vec::new(),
+            partition_ttl_seconds: DEFAULT_PARTITION_TTL_SECONDS,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T> ModalQueue<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Return a handle scoped to the named partition of the same queue.
+    pub fn partition(&self, key: &str) -> Self {
+        let mut scoped = self.clone();
+        scoped.partition_key = key.as_bytes().to_vec();
+        scoped
+    }
+
+    /// Set how long a partition is kept after its last `put`.
+    pub fn with_partition_ttl(mut self, ttl: Duration) -> Self {
+        self.partition_ttl_seconds = ttl.as_secs().min(i32::MAX as u64) as i32;
+        self
+    }
+
+    /// Append a value to the partition.
+    pub async fn put(&mut self, value: &T) -> Result<()> {
+        self.put_many(std::slice::from_ref(value)).await
+    }
+
+    /// Append several values to the partition in a single request.
+    pub async fn put_many(&mut self, values: &[T]) -> Result<()> {
+        let values = values.iter().map(to_cbor).collect::<Result<Vec<_>>>()?;
+        let req_msg = client::QueuePutRequest {
+            queue_id: self.queue_id.clone(),
+            values,
+            partition_key: self.partition_key.clone(),
+            partition_ttl_seconds: self.partition_ttl_seconds,
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.queue_put(req).await?;
+        Ok(())
+    }
+
+    /// Remove and return the next value, waiting up to `timeout` for one to arrive.
+    /// `None` waits indefinitely. Returns `Ok(None)` if the timeout elapses.
+    pub async fn get(&mut self, timeout: Option<Duration>) -> Result<Option<T>> {
+        Ok(self.get_many(1, timeout).await?.into_iter().next())
+    }
+
+    /// Remove and return up to `n` values, waiting up to `timeout` for at least one to arrive.
+    /// `None` waits indefinitely. Returns an empty vector if the timeout elapses.
+    pub async fn get_many(&mut self, n: usize, timeout: Option<Duration>) -> Result<Vec<T>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let poll = match deadline {
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(MAX_GET_POLL),
+                None => MAX_GET_POLL,
+            };
+            let req_msg = client::QueueGetRequest {
+                queue_id: self.queue_id.clone(),
+                timeout: poll.as_secs_f32(),
+                n_values: n.min(i32::MAX as usize) as i32,
+                partition_key: self.partition_key.clone(),
+            };
+            let req = self.client.make_request(req_msg);
+            let resp = self.client.stub.queue_get(req).await?.into_inner();
+            if !resp.values.is_empty() {
+                return resp.values.iter().map(|v| from_cbor(v)).collect();
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(Vec::new());
+            }
+        }
+    }
+
+    /// Number of values in the partition.
+    pub async fn len(&mut self) -> Result<usize> {
+        self.send_len(false).await
+    }
+
+    /// Number of values across all partitions.
+    pub async fn len_total(&mut self) -> Result<usize> {
+        self.send_len(true).await
+    }
+
+    /// Whether the partition has no values.
+    pub async fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Remove all values from the partition.
+    pub async fn clear(&mut self) -> Result<()> {
+        self.send_clear(false).await
+    }
+
+    /// Remove all values from every partition.
+    pub async fn clear_all(&mut self) -> Result<()> {
+        self.send_clear(true).await
+    }
+
+    /// Delete the queue and all of its partitions.
+    pub async fn delete(mut self) -> Result<()> {
+        let req_msg = client::QueueDeleteRequest {
+            queue_id: self.queue_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.queue_delete(req).await?;
+        Ok(())
+    }
+
+    /// Iterate over the partition without removing values, via `QueueNextItems`.
+    ///
+    /// Each request waits up to `item_poll_timeout` for new items; the stream ends once a
+    /// request returns nothing.
+    pub fn iter(&self, item_poll_timeout: Duration) -> impl Stream<Item = Result<T>> {
+        let state = QueueIter {
+            client: self.client.clone(),
+            queue_id: self.queue_id.clone(),
+            partition_key: self.partition_key.clone(),
+            item_poll_timeout,
+            last_entry_id: String::new(),
+        };
+        batch_stream(state).map(|value| value.and_then(|value| from_cbor(&value)))
+    }
+
+    async fn send_len(&mut self, total: bool) -> Result<usize> {
+        let req_msg = client::QueueLenRequest {
+            queue_id: self.queue_id.clone(),
+            partition_key: self.partition_key.clone(),
+            total,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.queue_len(req).await?.into_inner();
+        Ok(resp.len.max(0) as usize)
+    }
+
+    async fn send_clear(&mut self, all_partitions: bool) -> Result<()> {
+        let req_msg = client::QueueClearRequest {
+            queue_id: self.queue_id.clone(),
+            partition_key: self.partition_key.clone(),
+            all_partitions,
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.queue_clear(req).await?;
+        Ok(())
+    }
+}
+
+/// State carried between `QueueNextItems` requests by `ModalQueue::iter`.
+struct QueueIter {
+    client: crate::client::ModalClient,
+    queue_id: String,
+    partition_key: Vec<u8>,
+    item_poll_timeout: Duration,
+    last_entry_id: String,
+}
+
+impl BatchSource for QueueIter {
+    type Item = Vec<u8>;
+
+    async fn next_batch(&mut self, buffer: &mut VecDeque<Vec<u8>>) -> Result<bool> {
+        let req_msg = client::QueueNextItemsRequest {
+            queue_id: self.queue_id.clone(),
+            partition_key: self.partition_key.clone(),
+            last_entry_id: self.last_entry_id.clone(),
+            item_poll_timeout: self.item_poll_timeout.as_secs_f32(),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.queue_next_items(req).await?.into_inner();
+        let more = !resp.items.is_empty();
+        for item in resp.items {
+            self.last_entry_id = item.entry_id;
+            buffer.push_back(item.value);
+        }
+        Ok(more)
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::time::Duration;
+use tokio::time::sleep;
+use tonic::{Code, Status};
+
+/// Number of times a call, or a stream without progress, is retried after a transient error.
+pub(crate) const MAX_RETRIES: u32 = 8;
+
+/// Delay before the first retry; doubled after each attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Errors worth retrying, including a dropped connection mid-stream.
+pub(crate) fn is_transient(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unavailable | Code::DeadlineExceeded | Code::Internal | Code::Unknown
+    )
+}
+
+/// Exponential backoff across consecutive transient failures of one call or stream.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Backoff {
+    attempts: u32,
+}
+
+impl Backoff {
+    /// Wait before retrying after `status`, or return it as the error if it is not transient
+    /// or `MAX_RETRIES` attempts have been made since the last `reset`.
+    pub(crate) async fn retry(&mut self, status: Status) -> Result<()> {
+        match self.delay(&status) {
+            Some(delay) => {
+                sleep(delay).await;
+                Ok(())
+            }
+            None => Err(status.into()),
+        }
+    }
+
+    /// Start counting attempts again, after the call or stream made progress.
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+    }
+
+    fn delay(&mut self, status: &Status) -> Option<Duration> {
+        if !is_transient(status) || self.attempts >= MAX_RETRIES {
+            return None;
+        }
+        let delay = INITIAL_BACKOFF * 2u32.pow(self.attempts);
+        self.attempts += 1;
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_until_retries_run_out() {
+        let mut backoff = Backoff::default();
+        let unavailable = Status::unavailable("down");
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.delay(&unavailable)).collect();
+        assert_eq!(delays.len(), MAX_RETRIES as usize);
+        assert_eq!(delays[0], INITIAL_BACKOFF);
+        assert!(delays.windows(2).all(|w| w[1] == w[0] * 2));
+
+        backoff.reset();
+        assert_eq!(backoff.delay(&unavailable), Some(INITIAL_BACKOFF));
+    }
+
+    #[test]
+    fn backoff_does_not_retry_permanent_errors() {
+        let mut backoff = Backoff::default();
+        for status in [
+            Status::not_found("gone"),
+            Status::permission_denied("no"),
+            Status::invalid_argument("bad"),
+        ] {
+            assert_eq!(backoff.delay(&status), None, "{:?}", status.code());
+        }
+    }
+}
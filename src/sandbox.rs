@@ -0,0 +1,457 @@
+use anyhow::{anyhow, Result};
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::{final_result, ModalClient};
+use crate::command_router::router_available;
+use crate::proto::modal::client;
+use crate::proto::modal::client::generic_result::GenericStatus;
+use crate::proto::modal::client::network_access::NetworkAccessType;
+use crate::proto::modal::client::sandbox::OpenPortsOneof;
+use crate::sandbox_resources::{milli_cpu, parse_cidr, GpuSpec};
+use crate::sandbox_tunnel::TunnelType;
+
+/// Server-side wait used by each `SandboxWait` / `SandboxGetTaskId` request while blocking.
+pub(crate) const WAIT_POLL_SECS: f32 = 10.0;
+
+/// A running or finished Modal Sandbox.
+#[derive(Clone)]
+pub struct Sandbox {
+    pub sandbox_id: String,
+    client: ModalClient,
+    /// Whether `exec` talks to the Sandbox's command router instead of the control plane, or
+    /// `None` until that is detected for handles not returned by `SandboxBuilder::create`.
+    direct_commands: Option<bool>,
+    /// Index of the next `SandboxStdinWrite`, shared by every stdin writer of this handle and
+    /// its clones since the server drops writes whose index it has already seen.
+    stdin_index: Arc<AtomicU32>,
+}
+
+/// Builder for creating a `Sandbox`. Wraps the `Sandbox` proto definition.
+#[derive(Clone, Debug)]
+pub struct SandboxBuilder {
+    app_id: String,
+    definition: client::Sandbox,
+}
+
+/// How a Sandbox finished, decoded from its `GenericResult`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SandboxExitStatus {
+    pub status: SandboxStatus,
+    /// Exit code of the entrypoint, when it exited on its own.
+    pub exit_code: i32,
+    /// Error message reported for failures, if any.
+    pub exception: String,
+}
+
+/// Terminal state of a Sandbox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SandboxStatus {
+    Unspecified,
+    Success,
+    Failure,
+    Terminated,
+    Timeout,
+    InitFailure,
+    InternalFailure,
+    IdleTimeout,
+}
+
+impl SandboxExitStatus {
+    /// Whether the entrypoint exited successfully.
+    pub fn success(&self) -> bool {
+        self.status == SandboxStatus::Success
+    }
+}
+
+impl From<client::GenericResult> for SandboxExitStatus {
+    fn from(result: client::GenericResult) -> Self {
+        let status = match result.status() {
+            GenericStatus::Unspecified => SandboxStatus::Unspecified,
+            GenericStatus::Success => SandboxStatus::Success,
+            GenericStatus::Failure => SandboxStatus::Failure,
+            GenericStatus::Terminated => SandboxStatus::Terminated,
+            GenericStatus::Timeout => SandboxStatus::Timeout,
+            GenericStatus::InitFailure => SandboxStatus::InitFailure,
+            GenericStatus::InternalFailure => SandboxStatus::InternalFailure,
+            GenericStatus::IdleTimeout => SandboxStatus::IdleTimeout,
+        };
+        SandboxExitStatus {
+            status,
+            exit_code: result.exitcode,
+            exception: result.exception,
+        }
+    }
+}
+
+/// The result of a `SandboxWait`, if the Sandbox has finished.
+pub(crate) fn finished(result: Option<client::GenericResult>) -> Option<SandboxExitStatus> {
+    final_result(result).map(SandboxExitStatus::from)
+}
+
+impl SandboxBuilder {
+    /// Start a Sandbox definition in the App `app_id` running the image `image_id`.
+    pub fn new(app_id: &str, image_id: &str) -> Self {
+        SandboxBuilder {
+            app_id: app_id.to_string(),
+            definition: client::Sandbox {
+                image_id: image_id.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Command and arguments to run. Without one, the image's default command runs.
+    pub fn entrypoint<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.definition.entrypoint_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Attach a Mount, by id, e.g. from `Mount::from_local_dir`.
+    pub fn mount(mut self, mount_id: &str) -> Self {
+        self.definition.mount_ids.push(mount_id.to_string());
+        self
+    }
+
+    /// Inject the environment variables of a Secret, by id.
+    pub fn secret(mut self, secret_id: &str) -> Self {
+        self.definition.secret_ids.push(secret_id.to_string());
+        self
+    }
+
+    /// Maximum lifetime of the Sandbox.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.definition.timeout_secs = duration_secs(timeout);
+        self
+    }
+
+    /// Terminate the Sandbox after it has been idle for this long.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.definition.idle_timeout_secs = Some(duration_secs(timeout));
+        self
+    }
+
+    /// Working directory of the entrypoint.
+    pub fn workdir(mut self, workdir: &str) -> Self {
+        self.definition.workdir = Some(workdir.to_string());
+        self
+    }
+
+    /// Name the Sandbox so it can be found with `Sandbox::from_name`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.definition.name = Some(name.to_string());
+        self
+    }
+
+    /// Requested CPU, in cores. Fails unless `cores` is finite and positive.
+    pub fn cpu(mut self, cores: f64) -> Result<Self> {
+        self.resources().milli_cpu = milli_cpu(cores)?;
+        Ok(self)
+    }
+
+    /// Requested memory, in MiB.
+    pub fn memory_mb(mut self, memory_mb: u32) -> Self {
+        self.resources().memory_mb = memory_mb;
+        self
+    }
+
+    /// Requested CPU and the hard limit it may burst to, in cores.
+    pub fn cpu_with_limit(mut self, cores: f64, limit_cores: f64) -> Result<Self> {
+        let (request, limit) = (milli_cpu(cores)?, milli_cpu(limit_cores)?);
+        if request > limit {
+            return Err(anyhow!(
+                "CPU request {} must be at most the limit {}",
+                cores,
+                limit_cores
+            ));
+        }
+        let resources = self.resources();
+        resources.milli_cpu = request;
+        resources.milli_cpu_max = limit;
+        Ok(self)
+    }
+
+    /// Requested memory and the hard limit the Sandbox is killed at, in MiB.
+    pub fn memory_mb_with_limit(mut self, memory_mb: u32, limit_mb: u32) -> Result<Self> {
+        if memory_mb > limit_mb {
+            return Err(anyhow!(
+                "memory request {} MiB exceeds the limit {} MiB",
+                memory_mb,
+                limit_mb
+            ));
+        }
+        let resources = self.resources();
+        resources.memory_mb = memory_mb;
+        resources.memory_mb_max = limit_mb;
+        Ok(self)
+    }
+
+    /// Ephemeral disk size, in MiB.
+    pub fn ephemeral_disk_mb(mut self, disk_mb: u32) -> Self {
+        self.resources().ephemeral_disk_mb = disk_mb;
+        self
+    }
+
+    /// Attach GPUs, given as a `GpuSpec` string such as `"A100-80GB:2"` or `"T4"`.
+    pub fn gpu(mut self, spec: &str) -> Result<Self> {
+        let spec: GpuSpec = spec.parse()?;
+        self.resources().gpu_config = Some(spec.into());
+        Ok(self)
+    }
+
+    /// Attach RDMA network interfaces.
+    pub fn rdma(mut self, enabled: bool) -> Self {
+        self.resources().rdma = enabled;
+        self
+    }
+
+    /// Block all network access from the Sandbox.
+    pub fn block_network(mut self) -> Self {
+        self.definition.block_network = true;
+        self
+    }
+
+    /// Only allow outbound traffic to these CIDR ranges, e.g. `"10.0.0.0/8"`.
+    pub fn cidr_allowlist<I, S>(mut self, cidrs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let allowed_cidrs = cidrs
+            .into_iter()
+            .map(|c| parse_cidr(c.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        self.definition.network_access = Some(client::NetworkAccess {
+            network_access_type: NetworkAccessType::Allowlist as i32,
+            allowed_cidrs,
+        });
+        Ok(self)
+    }
+
+    /// Regions the Sandbox may be scheduled in, e.g. `"us-east"` or `"eu-west-1"`.
+    pub fn regions<I, S>(mut self, regions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.placement().regions = regions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only schedule the Sandbox on non-preemptible capacity.
+    pub fn nonpreemptible(mut self, enabled: bool) -> Self {
+        self.placement().nonpreemptible = enabled;
+        self
+    }
+
+    fn placement(&mut self) -> &mut client::SchedulerPlacement {
+        self.definition
+            .scheduler_placement
+            .get_or_insert_with(client::SchedulerPlacement::default)
+    }
+
+    /// Run `Sandbox::exec` commands through the command router on the Sandbox's worker rather
+    /// than through the control plane.
+    pub fn direct_sandbox_commands(mut self, enabled: bool) -> Self {
+        self.definition.direct_sandbox_commands_enabled = enabled;
+        self
+    }
+
+    /// Expose `port` in the Sandbox through a tunnel, listed by `Sandbox::tunnels`. With
+    /// `unencrypted`, the tunnel also gets a raw TCP endpoint next to the TLS one.
+    pub fn open_port(mut self, port: u16, unencrypted: bool, tunnel_type: TunnelType) -> Self {
+        let OpenPortsOneof::OpenPorts(specs) = self
+            .definition
+            .open_ports_oneof
+            .get_or_insert_with(|| OpenPortsOneof::OpenPorts(client::PortSpecs::default()));
+        specs.ports.push(client::PortSpec {
+            port: port as u32,
+            unencrypted,
+            tunnel_type: tunnel_type.to_proto(),
+        });
+        self
+    }
+
+    /// Allow memory snapshots of the Sandbox with `Sandbox::snapshot`.
+    pub fn enable_snapshot(mut self, enabled: bool) -> Self {
+        self.definition.enable_snapshot = enabled;
+        self
+    }
+
+    fn resources(&mut self) -> &mut client::Resources {
+        self.definition
+            .resources
+            .get_or_insert_with(client::Resources::default)
+    }
+
+    /// Reject combinations of settings that cannot be applied together.
+    fn validate(&self) -> Result<()> {
+        let allowlist = self
+            .definition
+            .network_access
+            .as_ref()
+            .is_some_and(|n| n.network_access_type() == NetworkAccessType::Allowlist);
+        if self.definition.block_network && allowlist {
+            return Err(anyhow!(
+                "block_network cannot be combined with cidr_allowlist"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Create the Sandbox.
+    pub async fn create(&self, client: &mut ModalClient) -> Result<Sandbox> {
+        self.validate()?;
+        let mut definition = self.definition.clone();
+        if definition.block_network {
+            definition.network_access = Some(client::NetworkAccess {
+                network_access_type: NetworkAccessType::Blocked as i32,
+                allowed_cidrs: vec![],
+            });
+        }
+        let req_msg = client::SandboxCreateRequest {
+            app_id: self.app_id.clone(),
+            definition: Some(definition),
+            environment_name: String::new(),
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.sandbox_create(req).await?.into_inner();
+        Ok(Sandbox {
+            sandbox_id: resp.sandbox_id,
+            client: client.clone(),
+            direct_commands: Some(self.definition.direct_sandbox_commands_enabled),
+            stdin_index: Arc::new(AtomicU32::new(1)),
+        })
+    }
+}
+
+/// Whole seconds of `d`, saturating at `u32::MAX`.
+pub(crate) fn duration_secs(d: Duration) -> u32 {
+    d.as_secs().min(u32::MAX as u64) as u32
+}
+
+impl Sandbox {
+    /// A handle to an existing Sandbox by id.
+    ///
+    /// Whether the Sandbox was created with direct sandbox commands is detected on the first
+    /// `exec`, by asking for access to its command router.
+    pub fn from_id(client: &ModalClient, sandbox_id: &str) -> Sandbox {
+        Sandbox {
+            sandbox_id: sandbox_id.to_string(),
+            client: client.clone(),
+            direct_commands: None,
+            stdin_index: Arc::new(AtomicU32::new(1)),
+        }
+    }
+
+    /// Set whether `exec` uses the command router, instead of detecting it on the first `exec`.
+    pub fn with_direct_commands(mut self, enabled: bool) -> Self {
+        self.direct_commands = Some(enabled);
+        self
+    }
+
+    pub(crate) fn client(&self) -> &ModalClient {
+        &self.client
+    }
+
+    pub(crate) fn client_mut(&mut self) -> &mut ModalClient {
+        &mut self.client
+    }
+
+    pub(crate) fn stdin_index(&self) -> Arc<AtomicU32> {
+        self.stdin_index.clone()
+    }
+
+    /// Whether `exec` should use the command router of `task_id`, detecting it once if unknown.
+    pub(crate) async fn direct_commands(&mut self, task_id: &str) -> Result<bool> {
+        if let Some(enabled) = self.direct_commands {
+            return Ok(enabled);
+        }
+        let enabled = router_available(&mut self.client, task_id).await?;
+        self.direct_commands = Some(enabled);
+        Ok(enabled)
+    }
+
+    /// Look up a running Sandbox by the name it was created with.
+    pub async fn from_name(
+        client: &mut ModalClient,
+        app_name: &str,
+        name: &str,
+    ) -> Result<Sandbox> {
+        let req_msg = client::SandboxGetFromNameRequest {
+            sandbox_name: name.to_string(),
+            environment_name: String::new(),
+            app_name: app_name.to_string(),
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.sandbox_get_from_name(req).await?.into_inner();
+        if resp.sandbox_id.is_empty() {
+            return Err(anyhow!("sandbox not found"));
+        }
+        Ok(Sandbox::from_id(client, &resp.sandbox_id))
+    }
+
+    /// Wait for the Sandbox to finish and return how it exited.
+    pub async fn wait(&mut self) -> Result<SandboxExitStatus> {
+        loop {
+            if let Some(status) = self.wait_for(WAIT_POLL_SECS).await? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Return how the Sandbox exited, or `None` if it is still running.
+    pub async fn poll(&mut self) -> Result<Option<SandboxExitStatus>> {
+        self.wait_for(0.0).await
+    }
+
+    /// Stop the Sandbox.
+    pub async fn terminate(&mut self) -> Result<()> {
+        let req_msg = client::SandboxTerminateRequest {
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.sandbox_terminate(req).await?;
+        Ok(())
+    }
+
+    /// Id of the task running the Sandbox, waiting for it to be scheduled and ready.
+    pub async fn task_id(&mut self) -> Result<String> {
+        loop {
+            let req_msg = client::SandboxGetTaskIdRequest {
+                sandbox_id: self.sandbox_id.clone(),
+                timeout: Some(WAIT_POLL_SECS),
+                wait_until_ready: true,
+            };
+            let req = self.client.make_request(req_msg);
+            let resp = self
+                .client
+                .stub
+                .sandbox_get_task_id(req)
+                .await?
+                .into_inner();
+            match (resp.task_id, finished(resp.task_result)) {
+                (Some(task_id), _) if !task_id.is_empty() => return Ok(task_id),
+                (_, Some(status)) => {
+                    return Err(anyhow!("sandbox has already finished: {:?}", status.status))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn wait_for(&mut self, timeout: f32) -> Result<Option<SandboxExitStatus>> {
+        let req_msg = client::SandboxWaitRequest {
+            sandbox_id: self.sandbox_id.clone(),
+            timeout,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.sandbox_wait(req).await?.into_inner();
+        Ok(finished(resp.result))
+    }
+}
@@ -0,0 +1,709 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tonic::codec::Streaming;
+use tonic::transport::Channel;
+use tonic::Status;
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::client::ModalClient;
+use crate::command_router::{Authorizer, CommandRouter};
+use crate::proto::modal::client;
+use crate::proto::modal::sandbox_router::sandbox_router_client::SandboxRouterClient;
+use crate::proto::modal::sandbox_router::{self as router};
+use crate::proto::modal::task_command_router::task_command_router_client::TaskCommandRouterClient;
+use crate::proto::modal::task_command_router::{self as task_router};
+use crate::retry::Backoff;
+use crate::sandbox::{duration_secs, Sandbox};
+use crate::sandbox_pty::PtySize;
+
+/// Server-side wait used by each `ContainerExecWait` / `ContainerExecGetOutput` request.
+const EXEC_POLL_SECS: f32 = 10.0;
+
+/// Options for `Sandbox::exec_with` and `ModalClient::task_exec_with`.
+#[derive(Clone, Debug, Default)]
+pub struct ExecOptions {
+    /// Working directory of the command.
+    pub workdir: Option<String>,
+    /// Kill the command if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Secrets, by id, whose environment variables are set for the command.
+    pub secret_ids: Vec<String>,
+    /// Send stderr to the stdout stream; `Process::stderr` is then empty.
+    pub stderr_to_stdout: bool,
+    /// Run the command in a pseudo-terminal of this size. Its output, including stderr, all
+    /// arrives on stdout.
+    pub pty: Option<PtySize>,
+}
+
+/// How an exec'd command exited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecExitStatus {
+    /// The command exited with this code.
+    Code(i32),
+    /// The command was killed by this signal.
+    Signal(i32),
+}
+
+impl ExecExitStatus {
+    /// Whether the command exited with code 0.
+    pub fn success(&self) -> bool {
+        *self == ExecExitStatus::Code(0)
+    }
+
+    /// The exit code, if the command was not killed by a signal.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ExecExitStatus::Code(code) => Some(*code),
+            ExecExitStatus::Signal(_) => None,
+        }
+    }
+}
+
+/// A command running inside a Sandbox or task container, started with `Sandbox::exec` or
+/// `ModalClient::task_exec`.
+///
+/// `stdout` and `stderr` can be moved out and consumed independently of `stdin` and `wait`.
+pub struct Process {
+    pub exec_id: String,
+    pub stdin: ProcessStdin,
+    pub stdout: ProcessOutput,
+    pub stderr: ProcessOutput,
+    backend: ExecBackend,
+}
+
+/// Output of a `Process`, as a stream of byte chunks that ends at EOF.
+pub struct ProcessOutput {
+    inner: BoxStream<'static, Result<Bytes>>,
+}
+
+impl Stream for ProcessOutput {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl ProcessOutput {
+    fn empty() -> Self {
+        ProcessOutput {
+            inner: stream::empty().boxed(),
+        }
+    }
+}
+
+/// Standard input of a `Process`.
+pub struct ProcessStdin {
+    backend: ExecBackend,
+    /// Bytes written so far (router) or index of the next message (control plane).
+    position: u64,
+    closed: bool,
+}
+
+/// Where exec RPCs go: a command router next to the task (the sandbox router when direct
+/// sandbox commands are enabled, or the task command router), otherwise the control-plane
+/// `ContainerExec*` RPCs.
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+enum ExecBackend {
+    Router {
+        router: CommandRouter<RouterStub>,
+        exec_id: String,
+    },
+    Control {
+        client: ModalClient,
+        exec_id: String,
+    },
+}
+
+impl Sandbox {
+    /// Run a command in the Sandbox with stdout and stderr piped back.
+    pub async fn exec<I, S>(&mut self, command: I) -> Result<Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exec_with(command, ExecOptions::default()).await
+    }
+
+    /// Run a command in the Sandbox with the given options.
+    pub async fn exec_with<I, S>(&mut self, command: I, opts: ExecOptions) -> Result<Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let command: Vec<String> = command.into_iter().map(Into::into).collect();
+        if command.is_empty() {
+            return Err(anyhow!("exec requires a command"));
+        }
+        let task_id = self.task_id().await?;
+        if self.direct_commands(&task_id).await? {
+            return router_exec(self.client(), &task_id, RouterStub::sandbox, command, opts).await;
+        }
+
+        let mut client = self.client().clone();
+        let stderr_to_stdout = opts.stderr_to_stdout || opts.pty.is_some();
+        let stderr_output = if stderr_to_stdout {
+            client::ExecOutputOption::Stdout
+        } else {
+            client::ExecOutputOption::Pipe
+        };
+        #[allow(deprecated)]
+        let req_msg = client::ContainerExecRequest {
+            task_id,
+            command,
+            pty_info: opts.pty.map(PtySize::to_proto),
+            terminate_container_on_exit: false,
+            runtime_debug: false,
+            stdout_output: client::ExecOutputOption::Pipe as i32,
+            stderr_output: stderr_output as i32,
+            timeout_secs: opts.timeout.map_or(0, duration_secs),
+            workdir: opts.workdir,
+            secret_ids: opts.secret_ids,
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.container_exec(req).await?.into_inner();
+        let backend = ExecBackend::Control {
+            client,
+            exec_id: resp.exec_id,
+        };
+        Ok(Process::new(backend, stderr_to_stdout))
+    }
+}
+
+/// Start `command` in task `task_id` through a command router.
+pub(crate) async fn router_exec(
+    client: &ModalClient,
+    task_id: &str,
+    make_stub: fn(Channel) -> RouterStub,
+    command: Vec<String>,
+    opts: ExecOptions,
+) -> Result<Process> {
+    let mut router = CommandRouter::connect(client, task_id, make_stub).await?;
+    let start = ExecStart {
+        task_id: task_id.to_string(),
+        exec_id: uuid::Uuid::new_v4().to_string(),
+        command_args: command,
+        stderr_to_stdout: opts.stderr_to_stdout || opts.pty.is_some(),
+        pty_info: opts.pty.map(PtySize::to_proto),
+        timeout_secs: opts.timeout.map(duration_secs),
+        workdir: opts.workdir,
+        secret_ids: opts.secret_ids,
+    };
+    router
+        .call(|stub, auth| stub.start(auth, start.clone()))
+        .await?;
+    let backend = ExecBackend::Router {
+        router,
+        exec_id: start.exec_id,
+    };
+    Ok(Process::new(backend, start.stderr_to_stdout))
+}
+
+impl Process {
+    fn new(backend: ExecBackend, stderr_to_stdout: bool) -> Process {
+        let stderr = if stderr_to_stdout {
+            ProcessOutput::empty()
+        } else {
+            backend.output(OutputFd::Stderr)
+        };
+        Process {
+            exec_id: backend.exec_id().to_string(),
+            stdin: ProcessStdin {
+                backend: backend.clone(),
+                position: 0,
+                closed: false,
+            },
+            stdout: backend.output(OutputFd::Stdout),
+            stderr,
+            backend,
+        }
+    }
+
+    /// Wait for the command to exit.
+    pub async fn wait(&mut self) -> Result<ExecExitStatus> {
+        self.backend.wait_for_exit().await
+    }
+
+    /// Return how the command exited, or `None` if it is still running.
+    pub async fn poll(&mut self) -> Result<Option<ExecExitStatus>> {
+        self.backend.poll().await
+    }
+
+    /// Split into stdin, stdout and a handle to wait on, dropping stderr.
+    pub(crate) fn into_parts(self) -> (ProcessStdin, ProcessOutput, ExecWaiter) {
+        let waiter = ExecWaiter {
+            backend: self.backend,
+        };
+        (self.stdin, self.stdout, waiter)
+    }
+}
+
+/// Waits for a process split up by `Process::into_parts` to exit.
+#[derive(Clone)]
+pub(crate) struct ExecWaiter {
+    backend: ExecBackend,
+}
+
+impl ExecWaiter {
+    pub(crate) async fn wait(&mut self) -> Result<ExecExitStatus> {
+        self.backend.wait_for_exit().await
+    }
+}
+
+impl ProcessStdin {
+    /// Write `data` to the command's standard input.
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.closed {
+            return Err(anyhow!("stdin is closed"));
+        }
+        self.send(data.to_vec(), false).await
+    }
+
+    /// Close the command's standard input, signalling EOF.
+    pub async fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.send(Vec::new(), true).await?;
+        self.closed = true;
+        Ok(())
+    }
+
+    async fn send(&mut self, data: Vec<u8>, eof: bool) -> Result<()> {
+        let len = data.len() as u64;
+        match &mut self.backend {
+            ExecBackend::Router { router, exec_id } => {
+                let task_id = router.task_id.clone();
+                let offset = self.position;
+                router
+                    .call(|stub, auth| {
+                        stub.stdin_write(
+                            auth,
+                            task_id.clone(),
+                            exec_id.clone(),
+                            offset,
+                            data.clone(),
+                            eof,
+                        )
+                    })
+                    .await?;
+                self.position += len;
+            }
+            ExecBackend::Control { client, exec_id } => {
+                // Control-plane stdin messages are numbered from 1.
+                self.position += 1;
+                let req_msg = client::ContainerExecPutInputRequest {
+                    exec_id: exec_id.clone(),
+                    input: Some(client::RuntimeInputMessage {
+                        message: data,
+                        message_index: self.position,
+                        eof,
+                    }),
+                };
+                let req = client.make_request(req_msg);
+                client.stub.container_exec_put_input(req).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum OutputFd {
+    Stdout,
+    Stderr,
+}
+
+impl ExecBackend {
+    fn exec_id(&self) -> &str {
+        match self {
+            ExecBackend::Router { exec_id, .. } | ExecBackend::Control { exec_id, .. } => exec_id,
+        }
+    }
+
+    async fn wait_for_exit(&mut self) -> Result<ExecExitStatus> {
+        loop {
+            if let Some(status) = self.wait(EXEC_POLL_SECS).await? {
+                return Ok(status);
+            }
+        }
+    }
+
+    async fn wait(&mut self, timeout: f32) -> Result<Option<ExecExitStatus>> {
+        match self {
+            ExecBackend::Router { router, exec_id } => {
+                let task_id = router.task_id.clone();
+                router
+                    .call(|stub, auth| stub.wait(auth, task_id.clone(), exec_id.clone()))
+                    .await
+            }
+            ExecBackend::Control { client, exec_id } => {
+                let req_msg = client::ContainerExecWaitRequest {
+                    exec_id: exec_id.clone(),
+                    timeout,
+                };
+                let req = client.make_request(req_msg);
+                let resp = client.stub.container_exec_wait(req).await?.into_inner();
+                if !resp.completed {
+                    return Ok(None);
+                }
+                match resp.exit_code {
+                    Some(code) => Ok(Some(ExecExitStatus::Code(code))),
+                    None => Err(anyhow!("exec finished without an exit code")),
+                }
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<Option<ExecExitStatus>> {
+        match self {
+            ExecBackend::Router { router, exec_id } => {
+                let task_id = router.task_id.clone();
+                router
+                    .call(|stub, auth| stub.poll(auth, task_id.clone(), exec_id.clone()))
+                    .await
+            }
+            ExecBackend::Control { .. } => self.wait(0.0).await,
+        }
+    }
+
+    fn output(&self, fd: OutputFd) -> ProcessOutput {
+        let state = OutputState {
+            backend: self.clone(),
+            fd,
+            position: 0,
+            router_stream: None,
+            control_stream: None,
+            backoff: Backoff::default(),
+        };
+        ProcessOutput {
+            inner: batch_stream(state).boxed(),
+        }
+    }
+}
+
+/// Read position in one output stream of a process. Router streams resume from a byte offset
+/// and control-plane streams from a batch index, so a dropped connection loses no output.
+struct OutputState {
+    backend: ExecBackend,
+    fd: OutputFd,
+    position: u64,
+    router_stream: Option<BoxStream<'static, std::result::Result<Vec<u8>, Status>>>,
+    control_stream: Option<Streaming<client::RuntimeOutputBatch>>,
+    backoff: Backoff,
+}
+
+impl BatchSource for OutputState {
+    type Item = Bytes;
+
+    /// Read the next response, buffering its data, and reopen the stream when needed.
+    async fn next_batch(&mut self, buffer: &mut VecDeque<Bytes>) -> Result<bool> {
+        // Whether the response made progress, and whether more output may follow.
+        let next = match &mut self.backend {
+            ExecBackend::Router { router, exec_id } => {
+                if self.router_stream.is_none() {
+                    let task_id = router.task_id.clone();
+                    let (offset, fd) = (self.position, self.fd);
+                    let stream = router
+                        .call(|stub, auth| {
+                            stub.stdio_read(auth, task_id.clone(), exec_id.clone(), offset, fd)
+                        })
+                        .await?;
+                    self.router_stream = Some(stream);
+                }
+                let stream = self.router_stream.as_mut().expect("stream was just opened");
+                match stream.try_next().await {
+                    Ok(Some(data)) => {
+                        self.position += data.len() as u64;
+                        if !data.is_empty() {
+                            buffer.push_back(Bytes::from(data));
+                        }
+                        Ok((true, true))
+                    }
+                    Ok(None) => Ok((true, false)),
+                    Err(status) => {
+                        self.router_stream = None;
+                        Err(status)
+                    }
+                }
+            }
+            ExecBackend::Control { client, exec_id } => {
+                if self.control_stream.is_none() {
+                    let file_descriptor = match self.fd {
+                        OutputFd::Stdout => client::FileDescriptor::Stdout,
+                        OutputFd::Stderr => client::FileDescriptor::Stderr,
+                    };
+                    let req_msg = client::ContainerExecGetOutputRequest {
+                        exec_id: exec_id.clone(),
+                        timeout: EXEC_POLL_SECS,
+                        last_batch_index: self.position,
+                        file_descriptor: file_descriptor as i32,
+                        get_raw_bytes: true,
+                    };
+                    let req = client.make_request(req_msg);
+                    let stream = client
+                        .stub
+                        .container_exec_get_output(req)
+                        .await?
+                        .into_inner();
+                    self.control_stream = Some(stream);
+                }
+                let stream = self
+                    .control_stream
+                    .as_mut()
+                    .expect("stream was just opened");
+                match stream.message().await {
+                    Ok(Some(batch)) => {
+                        self.position = batch.batch_index;
+                        for item in batch.items {
+                            let data = if item.message_bytes.is_empty() {
+                                item.message.into_bytes()
+                            } else {
+                                item.message_bytes
+                            };
+                            if !data.is_empty() {
+                                buffer.push_back(Bytes::from(data));
+                            }
+                        }
+                        Ok((true, batch.exit_code.is_none()))
+                    }
+                    // The server closes the stream after each poll window; reopen it.
+                    Ok(None) => {
+                        self.control_stream = None;
+                        Ok((false, true))
+                    }
+                    Err(status) => {
+                        self.control_stream = None;
+                        Err(status)
+                    }
+                }
+            }
+        };
+
+        match next {
+            Ok((progressed, more)) => {
+                if progressed {
+                    self.backoff.reset();
+                }
+                Ok(more)
+            }
+            Err(status) => self.backoff.retry(status).await.map(|()| true),
+        }
+    }
+}
+
+/// Parameters of an exec started through a command router.
+#[derive(Clone)]
+struct ExecStart {
+    task_id: String,
+    exec_id: String,
+    command_args: Vec<String>,
+    stderr_to_stdout: bool,
+    pty_info: Option<client::PtyInfo>,
+    timeout_secs: Option<u32>,
+    workdir: Option<String>,
+    secret_ids: Vec<String>,
+}
+
+/// Stub for either command router service. Both expose the same exec RPCs under different
+/// message types; this maps them onto one interface.
+#[derive(Clone)]
+pub(crate) enum RouterStub {
+    Sandbox(SandboxRouterClient<Channel>),
+    Task(TaskCommandRouterClient<Channel>),
+}
+
+type StatusResult<T> = std::result::Result<T, Status>;
+
+impl RouterStub {
+    pub(crate) fn sandbox(channel: Channel) -> RouterStub {
+        RouterStub::Sandbox(SandboxRouterClient::new(channel))
+    }
+
+    pub(crate) fn task(channel: Channel) -> RouterStub {
+        RouterStub::Task(TaskCommandRouterClient::new(channel))
+    }
+
+    async fn start(self, auth: Authorizer, start: ExecStart) -> StatusResult<()> {
+        match self {
+            RouterStub::Sandbox(mut stub) => {
+                let stderr_config = if start.stderr_to_stdout {
+                    router::SandboxExecStderrConfig::Stdout
+                } else {
+                    router::SandboxExecStderrConfig::Pipe
+                };
+                let req = auth.request(router::SandboxExecStartRequest {
+                    task_id: start.task_id,
+                    exec_id: start.exec_id,
+                    command_args: start.command_args,
+                    stdout_config: router::SandboxExecStdoutConfig::Pipe as i32,
+                    stderr_config: stderr_config as i32,
+                    timeout_secs: start.timeout_secs,
+                    workdir: start.workdir,
+                    secret_ids: start.secret_ids,
+                    pty_info: start.pty_info,
+                    runtime_debug: false,
+                });
+                stub.sandbox_exec_start(req).await?;
+            }
+            RouterStub::Task(mut stub) => {
+                let stderr_config = if start.stderr_to_stdout {
+                    task_router::TaskExecStderrConfig::Stdout
+                } else {
+                    task_router::TaskExecStderrConfig::Pipe
+                };
+                let req = auth.request(task_router::TaskExecStartRequest {
+                    task_id: start.task_id,
+                    exec_id: start.exec_id,
+                    command_args: start.command_args,
+                    stdout_config: task_router::TaskExecStdoutConfig::Pipe as i32,
+                    stderr_config: stderr_config as i32,
+                    timeout_secs: start.timeout_secs,
+                    workdir: start.workdir,
+                    secret_ids: start.secret_ids,
+                    pty_info: start.pty_info,
+                    runtime_debug: false,
+                });
+                stub.task_exec_start(req).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn stdin_write(
+        self,
+        auth: Authorizer,
+        task_id: String,
+        exec_id: String,
+        offset: u64,
+        data: Vec<u8>,
+        eof: bool,
+    ) -> StatusResult<()> {
+        match self {
+            RouterStub::Sandbox(mut stub) => {
+                let req = auth.request(router::SandboxExecStdinWriteRequest {
+                    task_id,
+                    exec_id,
+                    offset,
+                    data,
+                    eof,
+                });
+                stub.sandbox_exec_stdin_write(req).await?;
+            }
+            RouterStub::Task(mut stub) => {
+                let req = auth.request(task_router::TaskExecStdinWriteRequest {
+                    task_id,
+                    exec_id,
+                    offset,
+                    data,
+                    eof,
+                });
+                stub.task_exec_stdin_write(req).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn stdio_read(
+        self,
+        auth: Authorizer,
+        task_id: String,
+        exec_id: String,
+        offset: u64,
+        fd: OutputFd,
+    ) -> StatusResult<BoxStream<'static, StatusResult<Vec<u8>>>> {
+        match self {
+            RouterStub::Sandbox(mut stub) => {
+                let file_descriptor = match fd {
+                    OutputFd::Stdout => router::SandboxExecStdioFileDescriptor::Stdout,
+                    OutputFd::Stderr => router::SandboxExecStdioFileDescriptor::Stderr,
+                };
+                let req = auth.request(router::SandboxExecStdioReadRequest {
+                    task_id,
+                    exec_id,
+                    offset,
+                    file_descriptor: file_descriptor as i32,
+                });
+                let stream = stub.sandbox_exec_stdio_read(req).await?.into_inner();
+                Ok(stream.map_ok(|resp| resp.data).boxed())
+            }
+            RouterStub::Task(mut stub) => {
+                let file_descriptor = match fd {
+                    OutputFd::Stdout => task_router::TaskExecStdioFileDescriptor::Stdout,
+                    OutputFd::Stderr => task_router::TaskExecStdioFileDescriptor::Stderr,
+                };
+                let req = auth.request(task_router::TaskExecStdioReadRequest {
+                    task_id,
+                    exec_id,
+                    offset,
+                    file_descriptor: file_descriptor as i32,
+                });
+                let stream = stub.task_exec_stdio_read(req).await?.into_inner();
+                Ok(stream.map_ok(|resp| resp.data).boxed())
+            }
+        }
+    }
+
+    async fn wait(
+        self,
+        auth: Authorizer,
+        task_id: String,
+        exec_id: String,
+    ) -> StatusResult<Option<ExecExitStatus>> {
+        use router::sandbox_exec_wait_response::ExitStatus as SandboxExit;
+        use task_router::task_exec_wait_response::ExitStatus as TaskExit;
+        match self {
+            RouterStub::Sandbox(mut stub) => {
+                let req = auth.request(router::SandboxExecWaitRequest { task_id, exec_id });
+                let resp = stub.sandbox_exec_wait(req).await?.into_inner();
+                Ok(resp.exit_status.map(|s| match s {
+                    SandboxExit::Code(c) => ExecExitStatus::Code(c),
+                    SandboxExit::Signal(s) => ExecExitStatus::Signal(s),
+                }))
+            }
+            RouterStub::Task(mut stub) => {
+                let req = auth.request(task_router::TaskExecWaitRequest { task_id, exec_id });
+                let resp = stub.task_exec_wait(req).await?.into_inner();
+                Ok(resp.exit_status.map(|s| match s {
+                    TaskExit::Code(c) => ExecExitStatus::Code(c),
+                    TaskExit::Signal(s) => ExecExitStatus::Signal(s),
+                }))
+            }
+        }
+    }
+
+    async fn poll(
+        self,
+        auth: Authorizer,
+        task_id: String,
+        exec_id: String,
+    ) -> StatusResult<Option<ExecExitStatus>> {
+        use router::sandbox_exec_poll_response::ExitStatus as SandboxExit;
+        use task_router::task_exec_poll_response::ExitStatus as TaskExit;
+        match self {
+            RouterStub::Sandbox(mut stub) => {
+                let req = auth.request(router::SandboxExecPollRequest { task_id, exec_id });
+                let resp = stub.sandbox_exec_poll(req).await?.into_inner();
+                Ok(resp.exit_status.map(|s| match s {
+                    SandboxExit::Code(c) => ExecExitStatus::Code(c),
+                    SandboxExit::Signal(s) => ExecExitStatus::Signal(s),
+                }))
+            }
+            RouterStub::Task(mut stub) => {
+                let req = auth.request(task_router::TaskExecPollRequest { task_id, exec_id });
+                let resp = stub.task_exec_poll(req).await?.into_inner();
+                Ok(resp.exit_status.map(|s| match s {
+                    TaskExit::Code(c) => ExecExitStatus::Code(c),
+                    TaskExit::Signal(s) => ExecExitStatus::Signal(s),
+                }))
+            }
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use anyhow::{anyhow, Result};
+use futures::stream::Stream;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tonic::codec::Streaming;
+use tonic::Status;
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::proto::modal::client;
+use crate::proto::modal::client::container_filesystem_exec_request::FileExecRequestOneof;
+use crate::retry::Backoff;
+use crate::sandbox::Sandbox;
+
+/// Server-side wait used by each `ContainerFilesystemExecGetOutput` request.
+const OUTPUT_POLL_SECS: f32 = 55.0;
+
+/// Largest payload sent in one `ContainerFileWriteRequest`.
+const WRITE_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest payload requested by one `ContainerFileReadRequest`.
+const READ_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Number of times the output of a file operation is reopened in a row without receiving a
+/// new batch before the operation is abandoned.
+const MAX_IDLE_REOPENS: u32 = 8;
+
+/// Filesystem access inside a running Sandbox, returned by `Sandbox::fs`.
+///
+/// Operations run in the Sandbox's container through `ContainerFilesystemExec`, without
+/// starting a process.
+#[derive(Clone)]
+pub struct SandboxFs {
+    sandbox: Sandbox,
+    task_id: Option<String>,
+}
+
+/// A change reported by `SandboxFs::watch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileWatchEvent {
+    pub kind: FileWatchEventKind,
+    pub paths: Vec<String>,
+}
+
+/// Kind of a `FileWatchEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileWatchEventKind {
+    Unknown,
+    Access,
+    Create,
+    Modify,
+    Remove,
+}
+
+#[derive(Deserialize)]
+struct LsOutput {
+    paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct WatchOutput {
+    event_type: String,
+    paths: Vec<String>,
+}
+
+impl From<WatchOutput> for FileWatchEvent {
+    fn from(event: WatchOutput) -> Self {
+        let kind = match event.event_type.as_str() {
+            "Access" => FileWatchEventKind::Access,
+            "Create" => FileWatchEventKind::Create,
+            "Modify" => FileWatchEventKind::Modify,
+            "Remove" => FileWatchEventKind::Remove,
+            _ => FileWatchEventKind::Unknown,
+        };
+        FileWatchEvent {
+            kind,
+            paths: event.paths,
+        }
+    }
+}
+
+impl Sandbox {
+    /// Access the Sandbox's filesystem.
+    pub fn fs(&self) -> SandboxFs {
+        SandboxFs {
+            sandbox: self.clone(),
+            task_id: None,
+        }
+    }
+}
+
+impl SandboxFs {
+    /// Read the whole file at `path`.
+    pub async fn read(&mut self, path: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_to(path, &mut data).await?;
+        Ok(data)
+    }
+
+    /// Write `data` to the file at `path`, replacing its contents.
+    pub async fn write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.write_from(path, &mut &data[..]).await
+    }
+
+    /// List the entries of the directory at `path`.
+    pub async fn ls(&mut self, path: &str) -> Result<Vec<String>> {
+        let output = self
+            .run(FileExecRequestOneof::FileLsRequest(
+                client::ContainerFileLsRequest {
+                    path: path.to_string(),
+                },
+            ))
+            .await
+            .map_err(|e| anyhow!("failed to list '{}': {}", path, e))?;
+        let ls: LsOutput = serde_json::from_slice(&output)?;
+        Ok(ls.paths)
+    }
+
+    /// Create the directory `path`, and its missing parents if `parents` is set.
+    pub async fn mkdir(&mut self, path: &str, parents: bool) -> Result<()> {
+        self.run(FileExecRequestOneof::FileMkdirRequest(
+            client::ContainerFileMkdirRequest {
+                path: path.to_string(),
+                make_parents: parents,
+            },
+        ))
+        .await
+        .map_err(|e| anyhow!("failed to create '{}': {}", path, e))?;
+        Ok(())
+    }
+
+    /// Remove the file or empty directory at `path`, or a whole tree if `recursive` is set.
+    pub async fn rm(&mut self, path: &str, recursive: bool) -> Result<()> {
+        self.run(FileExecRequestOneof::FileRmRequest(
+            client::ContainerFileRmRequest {
+                path: path.to_string(),
+                recursive,
+            },
+        ))
+        .await
+        .map_err(|e| anyhow!("failed to remove '{}': {}", path, e))?;
+        Ok(())
+    }
+
+    /// Copy the local file `local_path` to `remote_path` in the Sandbox, streaming it in chunks.
+    pub async fn copy_from_local(
+        &mut self,
+        local_path: impl AsRef<Path>,
+        remote_path: &str,
+    ) -> Result<()> {
+        let local_path = local_path.as_ref();
+        let mut file = tokio::fs::File::open(local_path)
+            .await
+            .map_err(|e| anyhow!("failed to read '{}': {}", local_path.display(), e))?;
+        self.write_from(remote_path, &mut file).await
+    }
+
+    /// Copy `remote_path` in the Sandbox to the local file `local_path`, streaming it in chunks.
+    pub async fn copy_to_local(
+        &mut self,
+        remote_path: &str,
+        local_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let local_path = local_path.as_ref();
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(local_path)
+            .await
+            .map_err(|e| anyhow!("failed to write '{}': {}", local_path.display(), e))?;
+        self.read_to(remote_path, &mut file).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Stream change events under `path`. The stream ends after `timeout`, if given, or when
+    /// the Sandbox stops.
+    pub async fn watch(
+        &mut self,
+        path: &str,
+        recursive: bool,
+        timeout: Option<Duration>,
+    ) -> Result<impl Stream<Item = Result<FileWatchEvent>>> {
+        let resp = self
+            .exec(FileExecRequestOneof::FileWatchRequest(
+                client::ContainerFileWatchRequest {
+                    path: path.to_string(),
+                    recursive,
+                    timeout_secs: timeout.map(|t| t.as_secs()),
+                },
+            ))
+            .await?;
+
+        let state = WatchState {
+            output: ExecOutput::new(self.clone(), resp.exec_id, None),
+            line: Vec::new(),
+        };
+        Ok(batch_stream(state))
+    }
+
+    /// Copy the file at `path` into `writer`, in `READ_CHUNK_SIZE` reads.
+    async fn read_to<W: AsyncWrite + Unpin>(&mut self, path: &str, writer: &mut W) -> Result<()> {
+        let fd = self.open(path, "rb").await?;
+        let copied = self.copy_out(&fd, writer).await;
+        let closed = self.close(fd).await;
+        copied.map_err(|e| anyhow!("failed to read '{}': {}", path, e))?;
+        closed
+    }
+
+    async fn copy_out<W: AsyncWrite + Unpin>(&mut self, fd: &str, writer: &mut W) -> Result<()> {
+        loop {
+            let data = self
+                .run(FileExecRequestOneof::FileReadRequest(
+                    client::ContainerFileReadRequest {
+                        file_descriptor: fd.to_string(),
+                        n: Some(READ_CHUNK_SIZE),
+                    },
+                ))
+                .await?;
+            if data.is_empty() {
+                return Ok(());
+            }
+            writer.write_all(&data).await?;
+        }
+    }
+
+    /// Replace the file at `path` with the contents of `reader`, in `WRITE_CHUNK_SIZE` writes.
+    async fn write_from<R: AsyncRead + Unpin>(&mut self, path: &str, reader: &mut R) -> Result<()> {
+        let fd = self.open(path, "wb").await?;
+        let written = self.copy_in(&fd, reader).await;
+        let closed = self.close(fd).await;
+        written.map_err(|e| anyhow!("failed to write '{}': {}", path, e))?;
+        closed
+    }
+
+    async fn copy_in<R: AsyncRead + Unpin>(&mut self, fd: &str, reader: &mut R) -> Result<()> {
+        loop {
+            let mut chunk = Vec::new();
+            reader
+                .take(WRITE_CHUNK_SIZE)
+                .read_to_end(&mut chunk)
+                .await?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            self.run(FileExecRequestOneof::FileWriteRequest(
+                client::ContainerFileWriteRequest {
+                    file_descriptor: fd.to_string(),
+                    data: chunk,
+                },
+            ))
+            .await?;
+        }
+    }
+
+    async fn open(&mut self, path: &str, mode: &str) -> Result<String> {
+        let resp = self
+            .exec(FileExecRequestOneof::FileOpenRequest(
+                client::ContainerFileOpenRequest {
+                    file_descriptor: None,
+                    path: path.to_string(),
+                    mode: mode.to_string(),
+                },
+            ))
+            .await?;
+        self.output(&resp.exec_id)
+            .await
+            .map_err(|e| anyhow!("failed to open '{}': {}", path, e))?;
+        resp.file_descriptor
+            .ok_or_else(|| anyhow!("no file descriptor returned for '{}'", path))
+    }
+
+    async fn close(&mut self, file_descriptor: String) -> Result<()> {
+        self.run(FileExecRequestOneof::FileCloseRequest(
+            client::ContainerFileCloseRequest { file_descriptor },
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Run a filesystem request and collect its output.
+    async fn run(&mut self, request: FileExecRequestOneof) -> Result<Vec<u8>> {
+        let resp = self.exec(request).await?;
+        self.output(&resp.exec_id).await
+    }
+
+    async fn exec(
+        &mut self,
+        request: FileExecRequestOneof,
+    ) -> Result<client::ContainerFilesystemExecResponse> {
+        let task_id = match &self.task_id {
+            Some(task_id) => task_id.clone(),
+            None => {
+                let task_id = self.sandbox.task_id().await?;
+                self.task_id = Some(task_id.clone());
+                task_id
+            }
+        };
+        let req_msg = client::ContainerFilesystemExecRequest {
+            file_exec_request_oneof: Some(request),
+            task_id,
+        };
+        let client = self.sandbox.client_mut();
+        let req = client.make_request(req_msg);
+        Ok(client
+            .stub
+            .container_filesystem_exec(req)
+            .await?
+            .into_inner())
+    }
+
+    async fn output_stream(
+        &mut self,
+        exec_id: &str,
+    ) -> Result<Streaming<client::FilesystemRuntimeOutputBatch>, Status> {
+        let req_msg = client::ContainerFilesystemExecGetOutputRequest {
+            exec_id: exec_id.to_string(),
+            timeout: OUTPUT_POLL_SECS,
+        };
+        let client = self.sandbox.client_mut();
+        let req = client.make_request(req_msg);
+        Ok(client
+            .stub
+            .container_filesystem_exec_get_output(req)
+            .await?
+            .into_inner())
+    }
+
+    /// Collect the output of `exec_id` until EOF, failing if the operation reported an error.
+    async fn output(&mut self, exec_id: &str) -> Result<Vec<u8>> {
+        let mut output = ExecOutput::new(self.clone(), exec_id.to_string(), Some(MAX_IDLE_REOPENS));
+        let mut data = Vec::new();
+        while let Some(batch) = output.next().await? {
+            for chunk in batch.output {
+                data.extend_from_slice(&chunk);
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Output of one filesystem operation, reopened whenever the server closes the stream.
+///
+/// `ContainerFilesystemExecGetOutput` cannot resume from an offset, so batches are numbered by
+/// `batch_index` and any already seen are skipped after a reopen.
+struct ExecOutput {
+    fs: SandboxFs,
+    exec_id: String,
+    stream: Option<Streaming<client::FilesystemRuntimeOutputBatch>>,
+    last_batch_index: Option<u64>,
+    backoff: Backoff,
+    /// Reopens since the last new batch, and the limit on them, if any.
+    idle_reopens: u32,
+    max_idle_reopens: Option<u32>,
+    eof: bool,
+}
+
+impl ExecOutput {
+    fn new(fs: SandboxFs, exec_id: String, max_idle_reopens: Option<u32>) -> Self {
+        ExecOutput {
+            fs,
+            exec_id,
+            stream: None,
+            last_batch_index: None,
+            backoff: Backoff::default(),
+            idle_reopens: 0,
+            max_idle_reopens,
+            eof: false,
+        }
+    }
+
+    /// The next batch not seen before, or `None` after EOF. Fails if the operation reported an
+    /// error.
+    async fn next(&mut self) -> Result<Option<client::FilesystemRuntimeOutputBatch>> {
+        while !self.eof {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => match self.fs.output_stream(&self.exec_id).await {
+                    Ok(stream) => self.stream.insert(stream),
+                    Err(status) => {
+                        self.backoff.retry(status).await?;
+                        continue;
+                    }
+                },
+            };
+            match stream.message().await {
+                Ok(Some(batch)) => {
+                    self.backoff.reset();
+                    if self
+                        .last_batch_index
+                        .is_some_and(|last| batch.batch_index <= last)
+                    {
+                        continue;
+                    }
+                    self.last_batch_index = Some(batch.batch_index);
+                    self.idle_reopens = 0;
+                    check_error(&batch)?;
+                    self.eof = batch.eof;
+                    return Ok(Some(batch));
+                }
+                Ok(None) => {
+                    self.stream = None;
+                    self.idle_reopens += 1;
+                    if self
+                        .max_idle_reopens
+                        .is_some_and(|max| self.idle_reopens > max)
+                    {
+                        return Err(anyhow!(
+                            "no output from filesystem operation after {} attempts",
+                            self.idle_reopens
+                        ));
+                    }
+                }
+                Err(status) => {
+                    self.stream = None;
+                    self.backoff.retry(status).await?;
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn check_error(batch: &client::FilesystemRuntimeOutputBatch) -> Result<()> {
+    match &batch.error {
+        Some(error) => Err(anyhow!("{}", error.error_message)),
+        None => Ok(()),
+    }
+}
+
+/// Progress through the output of a watch, which arrives as newline-delimited JSON events.
+struct WatchState {
+    output: ExecOutput,
+    line: Vec<u8>,
+}
+
+impl BatchSource for WatchState {
+    type Item = FileWatchEvent;
+
+    async fn next_batch(&mut self, events: &mut VecDeque<FileWatchEvent>) -> Result<bool> {
+        let Some(batch) = self.output.next().await? else {
+            return Ok(false);
+        };
+        for chunk in &batch.output {
+            events.extend(split_watch_events(&mut self.line, chunk)?);
+        }
+        Ok(!batch.eof)
+    }
+}
+
+/// Append `chunk` to the partial line in `pending` and parse every complete line into an
+/// event, leaving any trailing partial line in `pending`.
+fn split_watch_events(pending: &mut Vec<u8>, chunk: &[u8]) -> Result<Vec<FileWatchEvent>> {
+    pending.extend_from_slice(chunk);
+    let mut events = Vec::new();
+    while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=end).collect();
+        let line = line.trim_ascii();
+        if !line.is_empty() {
+            let event: WatchOutput = serde_json::from_slice(line)?;
+            events.push(event.into());
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: FileWatchEventKind, paths: &[&str]) -> FileWatchEvent {
+        FileWatchEvent {
+            kind,
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn splits_several_events_in_one_chunk() {
+        let mut pending = Vec::new();
+        let chunk = concat!(
+            r#"{"event_type":"Create","paths":["/a"]}"#,
+            "\n",
+            r#"{"event_type":"Modify","paths":["/a","/b"]}"#,
+            "\n",
+        );
+        let events = split_watch_events(&mut pending, chunk.as_bytes()).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                event(FileWatchEventKind::Create, &["/a"]),
+                event(FileWatchEventKind::Modify, &["/a", "/b"]),
+            ]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn keeps_partial_lines_until_completed() {
+        let mut pending = Vec::new();
+        let line = r#"{"event_type":"Remove","paths":["/x"]}"#;
+        let (head, tail) = line.split_at(10);
+        assert!(split_watch_events(&mut pending, head.as_bytes())
+            .unwrap()
+            .is_empty());
+        assert_eq!(pending, head.as_bytes());
+        let events = split_watch_events(&mut pending, format!("{}\n", tail).as_bytes()).unwrap();
+        assert_eq!(events, vec![event(FileWatchEventKind::Remove, &["/x"])]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn skips_blank_lines_and_maps_unknown_kinds() {
+        let mut pending = Vec::new();
+        let chunk = "\n  \r\n{\"event_type\":\"Rename\",\"paths\":[]}\r\n";
+        let events = split_watch_events(&mut pending, chunk.as_bytes()).unwrap();
+        assert_eq!(events, vec![event(FileWatchEventKind::Unknown, &[])]);
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        let mut pending = Vec::new();
+        assert!(split_watch_events(&mut pending, b"not json\n").is_err());
+        let mut pending = Vec::new();
+        assert!(split_watch_events(&mut pending, b"{\"paths\":[]}\n").is_err());
+    }
+}
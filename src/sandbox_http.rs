@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Method, RequestBuilder};
+
+use crate::proto::modal::client;
+use crate::sandbox::Sandbox;
+
+/// Credentials for reaching a Sandbox's HTTP server directly, from
+/// `Sandbox::connect_token`. Requests to `url` must carry `Authorization: Bearer <token>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectToken {
+    pub url: String,
+    pub token: String,
+}
+
+/// A small HTTP client for a Sandbox's server that authenticates every request with a
+/// connect token, returned by `Sandbox::http_client`.
+#[derive(Clone)]
+pub struct SandboxHttpClient {
+    http: reqwest::Client,
+    credentials: ConnectToken,
+}
+
+impl Sandbox {
+    /// Create a connect token for the Sandbox's HTTP server. `user_metadata` is forwarded to
+    /// the server with each request so it can tell callers apart; pass `""` for none.
+    pub async fn connect_token(&mut self, user_metadata: &str) -> Result<ConnectToken> {
+        let req_msg = client::SandboxCreateConnectTokenRequest {
+            sandbox_id: self.sandbox_id.clone(),
+            user_metadata: user_metadata.to_string(),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        let resp = client
+            .stub
+            .sandbox_create_connect_token(req)
+            .await?
+            .into_inner();
+        if resp.url.is_empty() || resp.token.is_empty() {
+            return Err(anyhow!("no connect token returned for sandbox"));
+        }
+        Ok(ConnectToken {
+            url: resp.url,
+            token: resp.token,
+        })
+    }
+
+    /// Create a connect token and wrap it in an HTTP client.
+    pub async fn http_client(&mut self, user_metadata: &str) -> Result<SandboxHttpClient> {
+        let credentials = self.connect_token(user_metadata).await?;
+        Ok(SandboxHttpClient {
+            http: self.client().http.clone(),
+            credentials,
+        })
+    }
+}
+
+impl SandboxHttpClient {
+    /// The credentials requests are made with, e.g. to hand to a browser.
+    pub fn credentials(&self) -> &ConnectToken {
+        &self.credentials
+    }
+
+    /// Start an authenticated request to `path` on the Sandbox's server.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/{}",
+            self.credentials.url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        );
+        self.http
+            .request(method, url)
+            .bearer_auth(&self.credentials.token)
+    }
+
+    /// Start an authenticated GET request to `path`.
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.request(Method::GET, path)
+    }
+
+    /// Start an authenticated POST request to `path`.
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.request(Method::POST, path)
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
+use tokio_util::io::StreamReader;
+use tonic::codec::Streaming;
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::retry::Backoff;
+use crate::sandbox::Sandbox;
+
+/// Server-side wait used by each `SandboxGetLogs` request before the stream is reopened.
+const LOGS_POLL_SECS: f32 = 55.0;
+
+/// Standard input of a Sandbox's entrypoint, as an `AsyncWrite`.
+///
+/// Each `poll_write` starts one `SandboxStdinWrite` and accepts its buffer; the next write,
+/// `poll_flush` or `poll_shutdown` waits for it and reports its error, if any.
+/// `poll_shutdown` sends EOF.
+pub struct SandboxStdin {
+    client: ModalClient,
+    sandbox_id: String,
+    /// Index of the next message, shared with the Sandbox handle; the server orders and
+    /// deduplicates writes by it.
+    index: Arc<AtomicU32>,
+    /// The write in flight.
+    pending: Option<JoinHandle<Result<()>>>,
+    closed: bool,
+}
+
+/// Stdout or stderr of a Sandbox's entrypoint, as an `AsyncRead` that reaches EOF when the
+/// Sandbox's output ends.
+pub struct SandboxOutput {
+    inner: StreamReader<BoxStream<'static, io::Result<Bytes>>, Bytes>,
+}
+
+impl Sandbox {
+    /// Writer for the entrypoint's standard input.
+    pub fn stdin(&self) -> SandboxStdin {
+        SandboxStdin {
+            client: self.client().clone(),
+            sandbox_id: self.sandbox_id.clone(),
+            index: self.stdin_index(),
+            pending: None,
+            closed: false,
+        }
+    }
+
+    /// Reader for the entrypoint's standard output.
+    pub fn stdout(&self) -> SandboxOutput {
+        SandboxOutput::new(self, client::FileDescriptor::Stdout)
+    }
+
+    /// Reader for the entrypoint's standard error.
+    pub fn stderr(&self) -> SandboxOutput {
+        SandboxOutput::new(self, client::FileDescriptor::Stderr)
+    }
+}
+
+impl SandboxStdin {
+    fn send(&mut self, input: Vec<u8>, eof: bool) -> JoinHandle<Result<()>> {
+        let mut client = self.client.clone();
+        let req_msg = client::SandboxStdinWriteRequest {
+            sandbox_id: self.sandbox_id.clone(),
+            input,
+            index: self.index.fetch_add(1, Ordering::SeqCst),
+            eof,
+        };
+        tokio::spawn(async move {
+            let req = client.make_request(req_msg);
+            client.stub.sandbox_stdin_write(req).await?;
+            Ok(())
+        })
+    }
+
+    /// Wait for the write in flight, if any.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(handle) = self.pending.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = futures::ready!(handle.poll_unpin(cx));
+        self.pending = None;
+        Poll::Ready(match result {
+            Ok(sent) => sent.map_err(io::Error::other),
+            Err(join_error) => Err(io::Error::other(join_error)),
+        })
+    }
+}
+
+impl AsyncWrite for SandboxStdin {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_pending(cx))?;
+        if this.closed {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "sandbox stdin is closed",
+            )));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        this.pending = Some(this.send(buf.to_vec(), false));
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            futures::ready!(this.poll_pending(cx))?;
+            if this.closed {
+                return Poll::Ready(Ok(()));
+            }
+            this.pending = Some(this.send(Vec::new(), true));
+            this.closed = true;
+        }
+    }
+}
+
+/// Position in a Sandbox's log stream, resumed from the last entry id after reconnects.
+struct LogState {
+    client: ModalClient,
+    sandbox_id: String,
+    file_descriptor: client::FileDescriptor,
+    last_entry_id: String,
+    stream: Option<Streaming<client::TaskLogsBatch>>,
+    backoff: Backoff,
+}
+
+impl SandboxOutput {
+    fn new(sandbox: &Sandbox, file_descriptor: client::FileDescriptor) -> SandboxOutput {
+        let state = LogState {
+            client: sandbox.client().clone(),
+            sandbox_id: sandbox.sandbox_id.clone(),
+            file_descriptor,
+            last_entry_id: String::new(),
+            stream: None,
+            backoff: Backoff::default(),
+        };
+        let chunks = batch_stream(state).map_err(io::Error::other);
+        SandboxOutput {
+            inner: StreamReader::new(chunks.boxed()),
+        }
+    }
+}
+
+impl BatchSource for LogState {
+    type Item = Bytes;
+
+    /// Read the next batch of log lines, reopening the stream when the server closes it.
+    async fn next_batch(&mut self, buffer: &mut VecDeque<Bytes>) -> Result<bool> {
+        if self.stream.is_none() {
+            let req_msg = client::SandboxGetLogsRequest {
+                sandbox_id: self.sandbox_id.clone(),
+                file_descriptor: self.file_descriptor as i32,
+                timeout: LOGS_POLL_SECS,
+                last_entry_id: self.last_entry_id.clone(),
+            };
+            let req = self.client.make_request(req_msg);
+            match self.client.stub.sandbox_get_logs(req).await {
+                Ok(resp) => self.stream = Some(resp.into_inner()),
+                Err(status) => return self.backoff.retry(status).await.map(|()| true),
+            }
+        }
+        let stream = self.stream.as_mut().expect("stream was just opened");
+        match stream.message().await {
+            Ok(Some(batch)) => {
+                self.backoff.reset();
+                if !batch.entry_id.is_empty() {
+                    self.last_entry_id = batch.entry_id;
+                }
+                for item in batch.items {
+                    if !item.data.is_empty() {
+                        buffer.push_back(Bytes::from(item.data));
+                    }
+                }
+                Ok(!batch.eof)
+            }
+            Ok(None) => {
+                self.stream = None;
+                Ok(true)
+            }
+            Err(status) => {
+                self.stream = None;
+                self.backoff.retry(status).await.map(|()| true)
+            }
+        }
+    }
+}
+
+impl AsyncRead for SandboxOutput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncBufRead for SandboxOutput {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{anyhow, Result};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::batch_stream::{batch_stream, BatchSource};
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::sandbox::{finished, Sandbox, SandboxExitStatus};
+
+/// Number of Sandboxes terminated concurrently by `Sandboxes::terminate_all`.
+const TERMINATE_CONCURRENCY: usize = 8;
+
+/// Sandbox listing API, returned by `ModalClient::sandboxes`.
+#[derive(Clone)]
+pub struct Sandboxes {
+    client: ModalClient,
+}
+
+/// Which Sandboxes `Sandboxes::list` and `Sandboxes::terminate_all` select.
+#[derive(Clone, Debug, Default)]
+pub struct SandboxFilter {
+    /// Only Sandboxes in this App.
+    pub app_id: Option<String>,
+    /// Only Sandboxes carrying all of these tags.
+    pub tags: HashMap<String, String>,
+    /// Also return Sandboxes that have finished.
+    pub include_finished: bool,
+}
+
+/// Outcome of `Sandboxes::terminate_all`.
+#[derive(Debug, Default)]
+pub struct TerminateReport {
+    /// Number of Sandboxes terminated.
+    pub terminated: usize,
+    /// Sandboxes that could not be terminated, with the error for each.
+    pub errors: Vec<(String, anyhow::Error)>,
+}
+
+/// A Sandbox as returned by `Sandboxes::list`.
+#[derive(Clone, Debug)]
+pub struct SandboxInfo {
+    pub sandbox_id: String,
+    pub app_id: String,
+    /// Name given with `SandboxBuilder::name`, or empty.
+    pub name: String,
+    /// Creation time in seconds since the Unix epoch.
+    pub created_at: f64,
+    pub tags: HashMap<String, String>,
+    /// How the Sandbox exited, or `None` while it is running.
+    pub exit_status: Option<SandboxExitStatus>,
+}
+
+impl ModalClient {
+    /// Access the Sandbox listing API.
+    pub fn sandboxes(&self) -> Sandboxes {
+        Sandboxes {
+            client: self.clone(),
+        }
+    }
+}
+
+impl Sandboxes {
+    /// Stream the Sandboxes matching `filter`, newest first.
+    pub fn list(&self, filter: SandboxFilter) -> impl Stream<Item = Result<SandboxInfo>> {
+        let page = Page {
+            client: self.client.clone(),
+            filter,
+            cursor: Cursor::default(),
+        };
+        batch_stream(page)
+    }
+
+    /// Terminate every running Sandbox matching `filter`. A Sandbox that fails to terminate
+    /// does not stop the others; its error is collected in the report.
+    pub async fn terminate_all(&self, filter: SandboxFilter) -> Result<TerminateReport> {
+        let filter = SandboxFilter {
+            include_finished: false,
+            ..filter
+        };
+        let sandbox_ids: Vec<String> = self
+            .list(filter)
+            .map_ok(|info| info.sandbox_id)
+            .try_collect()
+            .await?;
+        let report = stream::iter(sandbox_ids)
+            .map(|sandbox_id| {
+                let mut sandbox = Sandbox::from_id(&self.client, &sandbox_id);
+                async move { (sandbox_id, sandbox.terminate().await) }
+            })
+            .buffer_unordered(TERMINATE_CONCURRENCY)
+            .fold(
+                TerminateReport::default(),
+                |mut report, (sandbox_id, result)| {
+                    match result {
+                        Ok(()) => report.terminated += 1,
+                        Err(e) => report.errors.push((sandbox_id, e)),
+                    }
+                    async move { report }
+                },
+            )
+            .await;
+        Ok(report)
+    }
+}
+
+impl Sandbox {
+    /// Replace the Sandbox's tags.
+    pub async fn set_tags(&mut self, tags: HashMap<String, String>) -> Result<()> {
+        let req_msg = client::SandboxTagsSetRequest {
+            environment_name: String::new(),
+            sandbox_id: self.sandbox_id.clone(),
+            tags: to_proto_tags(&tags),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        client.stub.sandbox_tags_set(req).await?;
+        Ok(())
+    }
+
+    /// The Sandbox's tags.
+    pub async fn tags(&mut self) -> Result<HashMap<String, String>> {
+        let req_msg = client::SandboxTagsGetRequest {
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        let resp = client.stub.sandbox_tags_get(req).await?.into_inner();
+        Ok(from_proto_tags(resp.tags))
+    }
+}
+
+/// Position in a `Sandboxes::list` listing.
+struct Page {
+    client: ModalClient,
+    filter: SandboxFilter,
+    cursor: Cursor,
+}
+
+/// Creation-time cursor for `SandboxList`, which returns Sandboxes created strictly before
+/// `before_timestamp`, newest first.
+///
+/// Sandboxes can share a creation time, so a page boundary may fall between them. The cursor
+/// therefore first asks for everything up to and including the oldest time seen, dropping the
+/// Sandboxes already returned at that time. If that page holds nothing new it moves strictly
+/// past that time, so a page full of Sandboxes sharing one time cannot stall the listing.
+#[derive(Default)]
+struct Cursor {
+    /// Oldest creation time returned so far.
+    oldest: Option<f64>,
+    /// Ids of the Sandboxes returned with creation time `oldest`.
+    seen_at_oldest: HashSet<String>,
+    /// Whether the next request excludes `oldest` itself.
+    strict: bool,
+}
+
+impl Cursor {
+    fn before_timestamp(&self) -> f64 {
+        match self.oldest {
+            None => 0.0,
+            Some(oldest) if self.strict => oldest,
+            Some(oldest) => oldest.next_up(),
+        }
+    }
+
+    /// The Sandboxes in `page` not returned before, advancing the cursor past them, or `None`
+    /// once the listing is exhausted.
+    fn advance(
+        &mut self,
+        page: Vec<client::SandboxInfo>,
+    ) -> Result<Option<Vec<client::SandboxInfo>>> {
+        let Some(last) = page.last().map(|info| info.created_at) else {
+            return Ok(None);
+        };
+        if let Some(oldest) = self.oldest.filter(|oldest| last > *oldest) {
+            return Err(anyhow!(
+                "sandbox listing moved backwards from {} to {}",
+                oldest,
+                last
+            ));
+        }
+        let fresh: Vec<client::SandboxInfo> = page
+            .into_iter()
+            .filter(|info| match self.oldest {
+                None => true,
+                Some(oldest) => {
+                    info.created_at < oldest
+                        || (info.created_at == oldest && !self.seen_at_oldest.contains(&info.id))
+                }
+            })
+            .collect();
+        if fresh.is_empty() {
+            // Nothing new: step strictly past the oldest time once, and stop if the server
+            // still has nothing new, such as when it keeps returning the same page.
+            if self.strict {
+                return Ok(None);
+            }
+            self.strict = true;
+            return Ok(Some(fresh));
+        }
+        if self.oldest != Some(last) {
+            self.oldest = Some(last);
+            self.seen_at_oldest.clear();
+        }
+        self.strict = false;
+        self.seen_at_oldest.extend(
+            fresh
+                .iter()
+                .filter(|info| info.created_at == last)
+                .map(|info| info.id.clone()),
+        );
+        Ok(Some(fresh))
+    }
+}
+
+impl BatchSource for Page {
+    type Item = SandboxInfo;
+
+    async fn next_batch(&mut self, buffer: &mut VecDeque<SandboxInfo>) -> Result<bool> {
+        let req_msg = client::SandboxListRequest {
+            app_id: self.filter.app_id.clone().unwrap_or_default(),
+            before_timestamp: self.cursor.before_timestamp(),
+            environment_name: String::new(),
+            include_finished: self.filter.include_finished,
+            tags: to_proto_tags(&self.filter.tags),
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = self.client.stub.sandbox_list(req).await?.into_inner();
+        match self.cursor.advance(resp.sandboxes)? {
+            Some(fresh) => {
+                buffer.extend(fresh.into_iter().map(SandboxInfo::from));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl From<client::SandboxInfo> for SandboxInfo {
+    fn from(info: client::SandboxInfo) -> Self {
+        SandboxInfo {
+            sandbox_id: info.id,
+            app_id: info.app_id,
+            name: info.name,
+            created_at: info.created_at,
+            tags: from_proto_tags(info.tags),
+            exit_status: info.task_info.and_then(|t| finished(t.result)),
+        }
+    }
+}
+
+fn to_proto_tags(tags: &HashMap<String, String>) -> Vec<client::SandboxTag> {
+    tags.iter()
+        .map(|(name, value)| client::SandboxTag {
+            tag_name: name.clone(),
+            tag_value: value.clone(),
+        })
+        .collect()
+}
+
+fn from_proto_tags(tags: Vec<client::SandboxTag>) -> HashMap<String, String> {
+    tags.into_iter()
+        .map(|tag| (tag.tag_name, tag.tag_value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: &str, created_at: f64) -> client::SandboxInfo {
+        client::SandboxInfo {
+            id: id.to_string(),
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    /// Page through `all` (newest first) as `SandboxList` would, `page_size` at a time.
+    fn list_with(all: &[client::SandboxInfo], page_size: usize) -> Vec<String> {
+        let mut cursor = Cursor::default();
+        let mut ids = Vec::new();
+        for _ in 0..100 {
+            let before = cursor.before_timestamp();
+            let page: Vec<_> = all
+                .iter()
+                .filter(|s| before == 0.0 || s.created_at < before)
+                .take(page_size)
+                .cloned()
+                .collect();
+            match cursor.advance(page).unwrap() {
+                Some(fresh) => ids.extend(fresh.into_iter().map(|s| s.id)),
+                None => return ids,
+            }
+        }
+        panic!("listing did not finish");
+    }
+
+    fn sandboxes() -> Vec<client::SandboxInfo> {
+        vec![
+            info("a", 9.0),
+            info("b", 7.0),
+            info("c", 7.0),
+            info("d", 7.0),
+            info("e", 5.0),
+            info("f", 5.0),
+            info("g", 1.0),
+        ]
+    }
+
+    #[test]
+    fn keeps_sandboxes_sharing_a_time_across_page_boundaries() {
+        let all = sandboxes();
+        let expected: Vec<String> = all.iter().map(|s| s.id.clone()).collect();
+        // Pages larger than the biggest group sharing a time see every Sandbox once.
+        for page_size in 4..=all.len() + 1 {
+            assert_eq!(
+                list_with(&all, page_size),
+                expected,
+                "page size {}",
+                page_size
+            );
+        }
+    }
+
+    #[test]
+    fn moves_past_a_page_full_of_one_time() {
+        // Smaller pages cannot reach every Sandbox sharing a time, but still finish and
+        // never repeat one.
+        for page_size in 1..4 {
+            let ids = list_with(&sandboxes(), page_size);
+            assert_eq!(ids.first().map(String::as_str), Some("a"));
+            assert_eq!(ids.last().map(String::as_str), Some("g"));
+            let unique: HashSet<&String> = ids.iter().collect();
+            assert_eq!(unique.len(), ids.len(), "page size {}", page_size);
+        }
+    }
+
+    #[test]
+    fn stops_when_the_server_repeats_a_page() {
+        let mut cursor = Cursor::default();
+        let page = vec![info("a", 3.0), info("b", 2.0)];
+        assert_eq!(cursor.advance(page.clone()).unwrap().unwrap().len(), 2);
+        assert_eq!(cursor.advance(page.clone()).unwrap().unwrap().len(), 0);
+        assert_eq!(cursor.before_timestamp(), 2.0);
+        assert!(cursor.advance(page).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_a_cursor_that_moves_backwards() {
+        let mut cursor = Cursor::default();
+        cursor
+            .advance(vec![info("a", 3.0), info("b", 2.0)])
+            .unwrap();
+        assert!(cursor.advance(vec![info("c", 4.0)]).is_err());
+    }
+}
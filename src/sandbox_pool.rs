@@ -0,0 +1,416 @@
+use anyhow::Result;
+use futures::future::{self, BoxFuture};
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::client::ModalClient;
+use crate::sandbox::{Sandbox, SandboxBuilder, SandboxExitStatus};
+
+/// Settings for a `SandboxPool`.
+#[derive(Clone, Debug)]
+pub struct SandboxPoolOptions {
+    /// Number of idle Sandboxes kept ready.
+    pub size: usize,
+    /// Replace idle Sandboxes older than this. Set it below the builder's `idle_timeout` so
+    /// leased Sandboxes are not about to be reaped by the server.
+    pub max_idle: Option<Duration>,
+    /// How often the pool checks idle Sandboxes and refills, besides after every lease.
+    pub check_interval: Duration,
+}
+
+impl Default for SandboxPoolOptions {
+    fn default() -> Self {
+        SandboxPoolOptions {
+            size: 4,
+            max_idle: None,
+            check_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Counters describing a `SandboxPool`, returned by `SandboxPool::metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Sandboxes waiting to be leased.
+    pub idle: u64,
+    /// Sandboxes currently leased out.
+    pub leased: u64,
+    /// Sandboxes created, for the pool or on demand.
+    pub created: u64,
+    /// Sandboxes terminated after use or recycling.
+    pub terminated: u64,
+    /// Leases served from an idle Sandbox.
+    pub hits: u64,
+    /// Leases that had to create a Sandbox because none was idle.
+    pub misses: u64,
+    /// Idle Sandboxes found finished by a health check and discarded.
+    pub unhealthy: u64,
+    /// Failed Sandbox creations.
+    pub create_errors: u64,
+    /// Failed Sandbox terminations; those Sandboxes stop once their timeout passes.
+    pub terminate_errors: u64,
+}
+
+/// Keeps warm Sandboxes created from a `SandboxBuilder` so requests do not wait for a cold
+/// start. `lease` hands one out; when the `SandboxLease` is dropped the Sandbox is terminated
+/// and the pool creates a replacement in the background.
+///
+/// Dropping the pool stops refilling; call `shutdown` to also terminate idle Sandboxes.
+pub struct SandboxPool {
+    inner: Arc<PoolInner>,
+    handle: JoinHandle<()>,
+}
+
+/// Checks whether a Sandbox has finished, `Sandbox::poll` outside of tests.
+type PollSandbox =
+    Arc<dyn Fn(Sandbox) -> BoxFuture<'static, Result<Option<SandboxExitStatus>>> + Send + Sync>;
+
+struct PoolInner {
+    client: ModalClient,
+    poll: PollSandbox,
+    builder: SandboxBuilder,
+    opts: SandboxPoolOptions,
+    idle: Mutex<VecDeque<IdleSandbox>>,
+    refill: Notify,
+    leased: AtomicU64,
+    created: AtomicU64,
+    terminated: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    unhealthy: AtomicU64,
+    create_errors: AtomicU64,
+    terminate_errors: AtomicU64,
+}
+
+struct IdleSandbox {
+    sandbox: Sandbox,
+    since: Instant,
+}
+
+/// A Sandbox leased from a `SandboxPool`. Terminated when dropped.
+pub struct SandboxLease {
+    sandbox: Option<Sandbox>,
+    pool: Arc<PoolInner>,
+}
+
+impl SandboxPool {
+    /// Start a pool of Sandboxes created from `builder`. Filling starts in the background.
+    pub fn new(
+        client: &ModalClient,
+        builder: SandboxBuilder,
+        opts: SandboxPoolOptions,
+    ) -> SandboxPool {
+        let inner = Arc::new(PoolInner::new(client, builder, opts));
+        let handle = tokio::spawn({
+            let inner = inner.clone();
+            async move {
+                loop {
+                    inner.maintain().await;
+                    let _ =
+                        tokio::time::timeout(inner.opts.check_interval, inner.refill.notified())
+                            .await;
+                }
+            }
+        });
+        SandboxPool { inner, handle }
+    }
+
+    /// Lease a healthy Sandbox, creating one if none is idle.
+    pub async fn lease(&self) -> Result<SandboxLease> {
+        let inner = &self.inner;
+        let sandbox = loop {
+            let next = inner.idle.lock().unwrap().pop_front();
+            let Some(mut idle) = next else {
+                inner.misses.fetch_add(1, Ordering::Relaxed);
+                break inner.create().await?;
+            };
+            if inner.healthy(&idle.sandbox).await {
+                inner.hits.fetch_add(1, Ordering::Relaxed);
+                break idle.sandbox;
+            }
+            inner.terminate(&mut idle.sandbox).await;
+        };
+        inner.leased.fetch_add(1, Ordering::Relaxed);
+        inner.refill.notify_one();
+        Ok(SandboxLease {
+            sandbox: Some(sandbox),
+            pool: inner.clone(),
+        })
+    }
+
+    /// A snapshot of the pool's counters.
+    pub fn metrics(&self) -> PoolMetrics {
+        let inner = &self.inner;
+        PoolMetrics {
+            idle: inner.idle.lock().unwrap().len() as u64,
+            leased: inner.leased.load(Ordering::Relaxed),
+            created: inner.created.load(Ordering::Relaxed),
+            terminated: inner.terminated.load(Ordering::Relaxed),
+            hits: inner.hits.load(Ordering::Relaxed),
+            misses: inner.misses.load(Ordering::Relaxed),
+            unhealthy: inner.unhealthy.load(Ordering::Relaxed),
+            create_errors: inner.create_errors.load(Ordering::Relaxed),
+            terminate_errors: inner.terminate_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stop refilling and terminate every idle Sandbox. Leased Sandboxes are terminated when
+    /// their leases are dropped.
+    pub async fn shutdown(self) {
+        self.handle.abort();
+        let idle: Vec<IdleSandbox> = self.inner.idle.lock().unwrap().drain(..).collect();
+        for mut idle in idle {
+            self.inner.terminate(&mut idle.sandbox).await;
+        }
+    }
+}
+
+impl Drop for SandboxPool {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl PoolInner {
+    fn new(client: &ModalClient, builder: SandboxBuilder, opts: SandboxPoolOptions) -> PoolInner {
+        PoolInner {
+            client: client.clone(),
+            poll: Arc::new(|mut sandbox: Sandbox| Box::pin(async move { sandbox.poll().await })),
+            builder,
+            opts,
+            idle: Mutex::new(VecDeque::new()),
+            refill: Notify::new(),
+            leased: AtomicU64::new(0),
+            created: AtomicU64::new(0),
+            terminated: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            unhealthy: AtomicU64::new(0),
+            create_errors: AtomicU64::new(0),
+            terminate_errors: AtomicU64::new(0),
+        }
+    }
+
+    async fn create(&self) -> Result<Sandbox> {
+        let mut client = self.client.clone();
+        match self.builder.create(&mut client).await {
+            Ok(sandbox) => {
+                self.created.fetch_add(1, Ordering::Relaxed);
+                Ok(sandbox)
+            }
+            Err(e) => {
+                self.create_errors.fetch_add(1, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the Sandbox is still running, checked with a zero-timeout `SandboxWait`. A
+    /// Sandbox that cannot be checked counts as finished. Callers terminate Sandboxes they
+    /// discard, since only they know whether it is still theirs to terminate.
+    async fn healthy(&self, sandbox: &Sandbox) -> bool {
+        if let Ok(None) = (self.poll)(sandbox.clone()).await {
+            return true;
+        }
+        self.unhealthy.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    async fn terminate(&self, sandbox: &mut Sandbox) {
+        // The Sandbox times out on its own if this fails, so there is nothing to retry.
+        match sandbox.terminate().await {
+            Ok(()) => self.terminated.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.terminate_errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// Drop expired and finished idle Sandboxes, then create Sandboxes up to the pool size.
+    async fn maintain(&self) {
+        // Check copies of the handles so leases can still take idle Sandboxes meanwhile.
+        let idle: Vec<(Sandbox, Instant)> = self
+            .idle
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|idle| (idle.sandbox.clone(), idle.since))
+            .collect();
+        for (mut sandbox, since) in idle {
+            let expired = self.opts.max_idle.is_some_and(|max| since.elapsed() >= max);
+            if !expired && self.healthy(&sandbox).await {
+                continue;
+            }
+            let removed = {
+                let mut queue = self.idle.lock().unwrap();
+                let before = queue.len();
+                queue.retain(|idle| idle.sandbox.sandbox_id != sandbox.sandbox_id);
+                queue.len() < before
+            };
+            // A lease may have taken the Sandbox while it was checked; it is no longer ours.
+            if removed {
+                self.terminate(&mut sandbox).await;
+            }
+        }
+
+        let missing = self
+            .opts
+            .size
+            .saturating_sub(self.idle.lock().unwrap().len());
+        // Failed creations are retried at the next check rather than hammering the API.
+        let created = future::join_all((0..missing).map(|_| self.create())).await;
+        let now = Instant::now();
+        self.idle
+            .lock()
+            .unwrap()
+            .extend(created.into_iter().flatten().map(|sandbox| IdleSandbox {
+                sandbox,
+                since: now,
+            }));
+    }
+}
+
+impl SandboxLease {
+    /// Keep the Sandbox instead of terminating it when the lease ends.
+    pub fn detach(mut self) -> Sandbox {
+        self.pool.leased.fetch_sub(1, Ordering::Relaxed);
+        self.pool.refill.notify_one();
+        self.sandbox.take().expect("lease holds a sandbox")
+    }
+}
+
+impl Deref for SandboxLease {
+    type Target = Sandbox;
+
+    fn deref(&self) -> &Sandbox {
+        self.sandbox.as_ref().expect("lease holds a sandbox")
+    }
+}
+
+impl DerefMut for SandboxLease {
+    fn deref_mut(&mut self) -> &mut Sandbox {
+        self.sandbox.as_mut().expect("lease holds a sandbox")
+    }
+}
+
+impl Drop for SandboxLease {
+    fn drop(&mut self) {
+        let Some(mut sandbox) = self.sandbox.take() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        pool.leased.fetch_sub(1, Ordering::Relaxed);
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            // Without a runtime the Sandbox cannot be terminated here; it stops on its own
+            // once its timeout passes.
+            log::warn!(
+                "sandbox {} leased from a pool was dropped outside a Tokio runtime; not terminating it",
+                sandbox.sandbox_id
+            );
+            return;
+        };
+        runtime.spawn(async move {
+            pool.terminate(&mut sandbox).await;
+            pool.refill.notify_one();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool around an unreachable client whose health checks fail, so every terminate
+    /// attempt shows up in `terminate_errors`.
+    fn failing_pool(poll: PollSandbox) -> PoolInner {
+        let client = ModalClient::for_tests();
+        PoolInner {
+            poll,
+            ..PoolInner::new(
+                &client,
+                SandboxBuilder::new("ap-test", "im-test"),
+                SandboxPoolOptions {
+                    size: 0,
+                    ..SandboxPoolOptions::default()
+                },
+            )
+        }
+    }
+
+    fn idle(pool: &PoolInner, sandbox_id: &str) {
+        pool.idle.lock().unwrap().push_back(IdleSandbox {
+            sandbox: Sandbox::from_id(&pool.client, sandbox_id),
+            since: Instant::now(),
+        });
+    }
+
+    #[tokio::test]
+    async fn maintain_terminates_unhealthy_idle_sandboxes() {
+        let pool = failing_pool(Arc::new(|_| {
+            Box::pin(async { Err(anyhow::anyhow!("poll failed")) })
+        }));
+        idle(&pool, "sb-idle");
+        pool.maintain().await;
+        assert!(pool.idle.lock().unwrap().is_empty());
+        assert_eq!(pool.unhealthy.load(Ordering::Relaxed), 1);
+        assert_eq!(pool.terminate_errors.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn maintain_does_not_terminate_a_sandbox_leased_during_its_check() {
+        let polling = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let pool = Arc::new(failing_pool(Arc::new({
+            let (polling, release) = (polling.clone(), release.clone());
+            move |_| {
+                let (polling, release) = (polling.clone(), release.clone());
+                Box::pin(async move {
+                    polling.notify_one();
+                    release.notified().await;
+                    Err(anyhow::anyhow!("poll failed"))
+                })
+            }
+        })));
+        idle(&pool, "sb-leased");
+
+        let maintain = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.maintain().await }
+        });
+        polling.notified().await;
+        // A lease takes the Sandbox while its health check is in flight.
+        let leased = pool.idle.lock().unwrap().pop_front().unwrap();
+        release.notify_one();
+        maintain.await.unwrap();
+
+        assert_eq!(leased.sandbox.sandbox_id, "sb-leased");
+        assert_eq!(pool.unhealthy.load(Ordering::Relaxed), 1);
+        assert_eq!(pool.terminated.load(Ordering::Relaxed), 0);
+        assert_eq!(pool.terminate_errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn dropping_a_lease_outside_a_runtime_does_not_panic() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let lease = runtime.block_on(async {
+            let client = ModalClient::for_tests();
+            let pool = Arc::new(PoolInner::new(
+                &client,
+                SandboxBuilder::new("ap-test", "im-test"),
+                SandboxPoolOptions::default(),
+            ));
+            pool.leased.store(1, Ordering::Relaxed);
+            SandboxLease {
+                sandbox: Some(Sandbox::from_id(&client, "sb-test")),
+                pool,
+            }
+        });
+        drop(runtime);
+        let pool = lease.pool.clone();
+        drop(lease);
+        assert_eq!(pool.leased.load(Ordering::Relaxed), 0);
+    }
+}
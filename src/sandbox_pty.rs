@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use crate::proto::modal::client;
+use crate::proto::modal::client::pty_info::PtyType;
+use crate::sandbox::Sandbox;
+use crate::sandbox_exec::{ExecExitStatus, ExecOptions, ExecWaiter, ProcessOutput, ProcessStdin};
+
+/// Size of a pseudo-terminal, in character cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PtySize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl Default for PtySize {
+    fn default() -> Self {
+        PtySize { rows: 24, cols: 80 }
+    }
+}
+
+impl PtySize {
+    pub(crate) fn to_proto(self) -> client::PtyInfo {
+        client::PtyInfo {
+            enabled: true,
+            winsz_rows: self.rows as u32,
+            winsz_cols: self.cols as u32,
+            env_term: std::env::var("TERM").unwrap_or_else(|_| "xterm-256color".to_string()),
+            env_colorterm: std::env::var("COLORTERM").unwrap_or_default(),
+            env_term_program: std::env::var("TERM_PROGRAM").unwrap_or_default(),
+            pty_type: PtyType::Shell as i32,
+            no_terminate_on_idle_stdin: true,
+        }
+    }
+}
+
+/// An interactive shell running in a pseudo-terminal inside a Sandbox, started with
+/// `Sandbox::attach_pty`.
+///
+/// Bytes written to `stdin` are keystrokes; `output` carries everything the terminal displays.
+pub struct PtySession {
+    pub stdin: ProcessStdin,
+    pub output: ProcessOutput,
+    control: PtyControl,
+}
+
+/// Resizes and waits on a `PtySession`; cloneable so it can be used while `stdin` and
+/// `output` are driven elsewhere.
+#[derive(Clone)]
+pub struct PtyControl {
+    sandbox: Sandbox,
+    /// File in the Sandbox holding the path of the shell's terminal device.
+    tty_file: String,
+    waiter: ExecWaiter,
+    resizes: Arc<ResizeQueue>,
+}
+
+/// Coalesces resizes so at most one resize exec runs at a time; sizes requested while one
+/// is running are collapsed into the latest, applied once it finishes.
+#[derive(Default)]
+struct ResizeQueue {
+    state: Mutex<ResizeState>,
+}
+
+#[derive(Default)]
+struct ResizeState {
+    pending: Option<PtySize>,
+    running: bool,
+}
+
+impl ResizeQueue {
+    /// Request `size`. If a resize is already running, return immediately and leave the
+    /// size for that caller to apply; otherwise apply sizes until none are pending.
+    async fn resize<F, Fut>(&self, size: PtySize, mut apply: F) -> Result<()>
+    where
+        F: FnMut(PtySize) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.pending = Some(size);
+            if state.running {
+                return Ok(());
+            }
+            state.running = true;
+        }
+        loop {
+            let next = {
+                let mut state = self.state.lock().unwrap();
+                let next = state.pending.take();
+                if next.is_none() {
+                    state.running = false;
+                }
+                next
+            };
+            let Some(size) = next else {
+                return Ok(());
+            };
+            if let Err(e) = apply(size).await {
+                let mut state = self.state.lock().unwrap();
+                state.pending = None;
+                state.running = false;
+                return Err(e);
+            }
+        }
+    }
+}
+
+impl Sandbox {
+    /// Start a login shell (bash if available, else sh) in a pseudo-terminal of `size`.
+    pub async fn attach_pty(&mut self, size: PtySize) -> Result<PtySession> {
+        let tty_file = format!("/tmp/.modal-pty-{}", uuid::Uuid::new_v4());
+        // Record the terminal device so `resize` can find it from a separate exec, and
+        // remove the record once the shell exits or the terminal hangs up.
+        let script = format!(
+            "f={}; tty > \"$f\"; trap 'rm -f \"$f\"' EXIT; trap 'exit 129' HUP; trap 'exit 143' TERM; \
+             if command -v bash >/dev/null 2>&1; then bash -l; else sh -l; fi",
+            tty_file
+        );
+        let opts = ExecOptions {
+            pty: Some(size),
+            ..Default::default()
+        };
+        let process = self.exec_with(["sh", "-c", &script], opts).await?;
+        let (stdin, output, waiter) = process.into_parts();
+        Ok(PtySession {
+            stdin,
+            output,
+            control: PtyControl {
+                sandbox: self.clone(),
+                tty_file,
+                waiter,
+                resizes: Arc::default(),
+            },
+        })
+    }
+}
+
+impl PtySession {
+    /// A handle for resizing the terminal and waiting for the shell to exit.
+    pub fn control(&self) -> PtyControl {
+        self.control.clone()
+    }
+
+    /// Change the terminal size; the shell receives `SIGWINCH`.
+    pub async fn resize(&mut self, size: PtySize) -> Result<()> {
+        self.control.resize(size).await
+    }
+
+    /// Wait for the shell to exit.
+    pub async fn wait(&mut self) -> Result<ExecExitStatus> {
+        self.control.wait().await
+    }
+}
+
+impl PtyControl {
+    /// Change the terminal size; the shell receives `SIGWINCH`. Resizes requested while
+    /// another is in progress, from any clone, are coalesced into the latest size.
+    pub async fn resize(&mut self, size: PtySize) -> Result<()> {
+        self.resizes
+            .resize(size, |size| {
+                set_size(self.sandbox.clone(), self.tty_file.clone(), size)
+            })
+            .await
+    }
+
+    /// Wait for the shell to exit.
+    pub async fn wait(&mut self) -> Result<ExecExitStatus> {
+        self.waiter.wait().await
+    }
+}
+
+/// Set the size of the terminal whose device path is recorded in `tty_file`.
+async fn set_size(mut sandbox: Sandbox, tty_file: String, size: PtySize) -> Result<()> {
+    // Setting the window size on the terminal device makes the kernel signal the
+    // foreground process group, as a local terminal emulator would.
+    let script = format!(
+        "stty -F \"$(cat {})\" rows {} cols {}",
+        tty_file, size.rows, size.cols
+    );
+    let mut process = sandbox.exec(["sh", "-c", &script]).await?;
+    let status = process.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("failed to resize terminal: {:?}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn coalesces_concurrent_resizes() {
+        let queue = Arc::new(ResizeQueue::default());
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let size = |rows| PtySize { rows, cols: 80 };
+        let apply = |applied: Arc<Mutex<Vec<u16>>>| {
+            move |size: PtySize| {
+                let applied = applied.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    applied.lock().unwrap().push(size.rows);
+                    Ok(())
+                }
+            }
+        };
+
+        let first = {
+            let queue = queue.clone();
+            let apply = apply(applied.clone());
+            tokio::spawn(async move { queue.resize(size(10), apply).await })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        for rows in [11, 12, 13] {
+            queue
+                .resize(size(rows), apply(applied.clone()))
+                .await
+                .unwrap();
+        }
+        first.await.unwrap().unwrap();
+        assert_eq!(*applied.lock().unwrap(), vec![10, 13]);
+
+        queue
+            .resize(size(14), apply(applied.clone()))
+            .await
+            .unwrap();
+        assert_eq!(*applied.lock().unwrap(), vec![10, 13, 14]);
+    }
+
+    #[tokio::test]
+    async fn failed_resize_does_not_block_later_ones() {
+        let queue = ResizeQueue::default();
+        let size = PtySize::default();
+        let err = queue
+            .resize(size, |_| async { Err(anyhow!("exec failed")) })
+            .await;
+        assert!(err.is_err());
+        queue.resize(size, |_| async { Ok(()) }).await.unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::proto::modal::client;
+
+/// A GPU request: a GPU type and how many of it, parsed from strings like `"A100-80GB:2"` or
+/// `"H100"` (a count of 1).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuSpec {
+    pub gpu_type: String,
+    pub count: u32,
+}
+
+impl FromStr for GpuSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (gpu_type, count) = match s.split_once(':') {
+            Some((gpu_type, count)) => {
+                let count = count
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| anyhow!("invalid GPU count in '{}'", s))?;
+                (gpu_type, count)
+            }
+            None => (s, 1),
+        };
+        let gpu_type = gpu_type.trim();
+        if gpu_type.is_empty()
+            || !gpu_type
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!("invalid GPU type in '{}'", s));
+        }
+        if count == 0 {
+            return Err(anyhow!("GPU count must be at least 1 in '{}'", s));
+        }
+        Ok(GpuSpec {
+            gpu_type: gpu_type.to_ascii_uppercase(),
+            count,
+        })
+    }
+}
+
+impl fmt::Display for GpuSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.gpu_type, self.count)
+    }
+}
+
+impl From<GpuSpec> for client::GpuConfig {
+    fn from(spec: GpuSpec) -> Self {
+        client::GpuConfig {
+            count: spec.count,
+            gpu_type: spec.gpu_type,
+            ..Default::default()
+        }
+    }
+}
+
+/// Convert a CPU core count to milli-CPUs, rejecting counts that are not finite and positive
+/// or that round to zero.
+pub(crate) fn milli_cpu(cores: f64) -> Result<u32> {
+    let milli = (cores * 1000.0).round();
+    if !cores.is_finite() || milli < 1.0 || milli > u32::MAX as f64 {
+        return Err(anyhow!(
+            "CPU cores must be a positive, finite number, got {}",
+            cores
+        ));
+    }
+    Ok(milli as u32)
+}
+
+/// Check that `cidr` is an IPv4 or IPv6 network in `address/prefix` form, returning it in
+/// canonical form: surrounding whitespace removed, the address normalized and its host bits
+/// cleared, so `10.1.2.3/8` becomes `10.0.0.0/8`.
+pub(crate) fn parse_cidr(cidr: &str) -> Result<String> {
+    let (addr, prefix) = cidr
+        .trim()
+        .split_once('/')
+        .ok_or_else(|| anyhow!("invalid CIDR '{}': expected address/prefix", cidr))?;
+    let addr: IpAddr = addr
+        .parse()
+        .map_err(|_| anyhow!("invalid CIDR '{}': bad address", cidr))?;
+    let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix: u8 = prefix
+        .parse()
+        .ok()
+        .filter(|p| *p <= max_prefix)
+        .ok_or_else(|| anyhow!("invalid CIDR '{}': prefix must be 0-{}", cidr, max_prefix))?;
+    let network = match addr {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    };
+    Ok(format!("{}/{}", network, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gpu_specs() {
+        let cases = [
+            ("H100", "H100", 1),
+            ("a100-80gb:2", "A100-80GB", 2),
+            (" t4 : 4 ", "T4", 4),
+            ("L40S:1", "L40S", 1),
+            ("any_gpu", "ANY_GPU", 1),
+        ];
+        for (input, gpu_type, count) in cases {
+            let spec: GpuSpec = input.parse().unwrap();
+            assert_eq!(spec.gpu_type, gpu_type, "{}", input);
+            assert_eq!(spec.count, count, "{}", input);
+        }
+        assert_eq!("a10g:3".parse::<GpuSpec>().unwrap().to_string(), "A10G:3");
+    }
+
+    #[test]
+    fn rejects_invalid_gpu_specs() {
+        for input in [
+            "", ":2", "H100:", "H100:0", "H100:-1", "H100:two", "H100:2:3", "H 100", "H100/2",
+        ] {
+            assert!(input.parse::<GpuSpec>().is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn converts_cpu_cores_to_milli_cpu() {
+        let cases = [
+            (1.0, 1000),
+            (0.25, 250),
+            (0.001, 1),
+            (2.0005, 2001),
+            (64.0, 64_000),
+        ];
+        for (cores, expected) in cases {
+            assert_eq!(milli_cpu(cores).unwrap(), expected, "{}", cores);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_cpu_cores() {
+        for cores in [
+            0.0,
+            -0.0,
+            -1.0,
+            0.0004,
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            1e10,
+        ] {
+            assert!(milli_cpu(cores).is_err(), "{}", cores);
+        }
+    }
+
+    #[test]
+    fn canonicalizes_cidrs() {
+        let cases = [
+            ("10.0.0.0/8", "10.0.0.0/8"),
+            ("10.1.2.3/8", "10.0.0.0/8"),
+            (" 192.168.1.77/24 ", "192.168.1.0/24"),
+            ("1.2.3.4/32", "1.2.3.4/32"),
+            ("1.2.3.4/0", "0.0.0.0/0"),
+            ("2001:db8::1/32", "2001:db8::/32"),
+            ("2001:0db8:0000::/48", "2001:db8::/48"),
+            ("::1/128", "::1/128"),
+            ("fe80::1/0", "::/0"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_cidr(input).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_cidrs() {
+        for input in [
+            "",
+            "10.0.0.0",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "10.0.0.0/-1",
+            "10.0.0/8",
+            "host.example/8",
+            "::1/129",
+            "10.0.0.0/8/8",
+        ] {
+            assert!(parse_cidr(input).is_err(), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::proto::modal::client::sandbox_restore_request::SandboxNameOverrideType;
+use crate::sandbox::{finished, Sandbox, SandboxExitStatus, WAIT_POLL_SECS};
+
+/// Name given to a Sandbox restored with `Sandbox::restore`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum RestoreName {
+    /// Keep the name of the snapshotted Sandbox.
+    #[default]
+    Original,
+    /// Leave the restored Sandbox unnamed, so one snapshot can be restored many times.
+    Unnamed,
+    /// Use this name instead.
+    Named(String),
+}
+
+impl Sandbox {
+    /// Snapshot the Sandbox's filesystem as an image and return the image id, which can be
+    /// passed to `SandboxBuilder::new` to start new Sandboxes from it.
+    pub async fn snapshot_filesystem(&mut self) -> Result<String> {
+        let req_msg = client::SandboxSnapshotFsAsyncRequest {
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        let image_id = client
+            .stub
+            .sandbox_snapshot_fs_async(req)
+            .await?
+            .into_inner()
+            .image_id;
+
+        loop {
+            let req_msg = client::SandboxSnapshotFsAsyncGetRequest {
+                image_id: image_id.clone(),
+                timeout: WAIT_POLL_SECS,
+            };
+            let req = client.make_request(req_msg);
+            let resp = client
+                .stub
+                .sandbox_snapshot_fs_async_get(req)
+                .await?
+                .into_inner();
+            if let Some(status) = finished(resp.result) {
+                check_snapshot("filesystem snapshot", &status)?;
+                return Ok(image_id);
+            }
+        }
+    }
+
+    /// Take a memory snapshot of the Sandbox and return the snapshot id once it is ready.
+    /// The Sandbox must have been created with `SandboxBuilder::enable_snapshot`.
+    pub async fn snapshot(&mut self) -> Result<String> {
+        let req_msg = client::SandboxSnapshotRequest {
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        let snapshot_id = client
+            .stub
+            .sandbox_snapshot(req)
+            .await?
+            .into_inner()
+            .snapshot_id;
+
+        loop {
+            let req_msg = client::SandboxSnapshotWaitRequest {
+                snapshot_id: snapshot_id.clone(),
+                timeout: WAIT_POLL_SECS,
+            };
+            let req = client.make_request(req_msg);
+            let resp = client.stub.sandbox_snapshot_wait(req).await?.into_inner();
+            if let Some(status) = finished(resp.result) {
+                check_snapshot("snapshot", &status)?;
+                return Ok(snapshot_id);
+            }
+        }
+    }
+
+    /// Start a new Sandbox from the memory snapshot `snapshot_id`.
+    pub async fn restore(
+        client: &mut ModalClient,
+        snapshot_id: &str,
+        name: RestoreName,
+    ) -> Result<Sandbox> {
+        let (override_type, name_override) = match name {
+            RestoreName::Original => (SandboxNameOverrideType::Unspecified, String::new()),
+            RestoreName::Unnamed => (SandboxNameOverrideType::None, String::new()),
+            RestoreName::Named(name) => (SandboxNameOverrideType::String, name),
+        };
+        let req_msg = client::SandboxRestoreRequest {
+            snapshot_id: snapshot_id.to_string(),
+            sandbox_name_override: name_override,
+            sandbox_name_override_type: override_type as i32,
+        };
+        let req = client.make_request(req_msg);
+        match client.stub.sandbox_restore(req).await {
+            Ok(resp) => Ok(Sandbox::from_id(client, &resp.into_inner().sandbox_id)),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                Err(anyhow!("snapshot '{}' not found", snapshot_id))
+            }
+            Err(status) => Err(status.into()),
+        }
+    }
+}
+
+fn check_snapshot(what: &str, status: &SandboxExitStatus) -> Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} failed: {:?} {}",
+            what,
+            status.status,
+            status.exception
+        ))
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{copy_bidirectional, AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::{self, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+use crate::proto::modal::client;
+use crate::proto::modal::client::generic_result::GenericStatus;
+use crate::sandbox::{Sandbox, WAIT_POLL_SECS};
+
+/// Protocol of a tunnel opened with `SandboxBuilder::open_port`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TunnelType {
+    /// Let the server choose.
+    #[default]
+    Default,
+    /// HTTP/2 tunnel.
+    H2,
+}
+
+impl TunnelType {
+    pub(crate) fn to_proto(self) -> Option<i32> {
+        match self {
+            TunnelType::Default => None,
+            TunnelType::H2 => Some(client::TunnelType::H2 as i32),
+        }
+    }
+}
+
+/// A public endpoint forwarding to a port inside a Sandbox.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tunnel {
+    pub container_port: u16,
+    /// TLS endpoint.
+    pub host: String,
+    pub port: u16,
+    /// Raw TCP endpoint, present when the port was opened with `unencrypted`.
+    pub unencrypted_host: Option<String>,
+    pub unencrypted_port: Option<u16>,
+}
+
+impl Tunnel {
+    /// HTTPS URL of the tunnel.
+    pub fn url(&self) -> String {
+        if self.port == 443 {
+            format!("https://{}", self.host)
+        } else {
+            format!("https://{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Host and port of the raw TCP endpoint, if there is one.
+    pub fn tcp_socket(&self) -> Option<(&str, u16)> {
+        match (&self.unencrypted_host, self.unencrypted_port) {
+            (Some(host), Some(port)) => Some((host, port)),
+            _ => None,
+        }
+    }
+}
+
+impl From<client::TunnelData> for Tunnel {
+    fn from(data: client::TunnelData) -> Self {
+        Tunnel {
+            container_port: data.container_port as u16,
+            host: data.host,
+            port: data.port as u16,
+            unencrypted_host: data.unencrypted_host,
+            unencrypted_port: data.unencrypted_port.map(|p| p as u16),
+        }
+    }
+}
+
+/// A local TCP listener proxying connections to a Sandbox tunnel, started with
+/// `Sandbox::forward_local`. Forwarding stops when this is dropped.
+pub struct PortForward {
+    local_addr: SocketAddr,
+    handle: JoinHandle<Result<()>>,
+    errors: mpsc::UnboundedReceiver<anyhow::Error>,
+}
+
+impl PortForward {
+    /// Address the local listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait for the next proxied connection to fail and return its error. A failed
+    /// connection only affects that client; the listener keeps serving the others.
+    /// Returns `None` once the listener and all its connections have stopped.
+    pub async fn next_error(&mut self) -> Option<anyhow::Error> {
+        self.errors.recv().await
+    }
+
+    /// Stop accepting connections and wait for the listener to shut down. Connections
+    /// already proxied keep running until closed. Returns the error that stopped the
+    /// listener if it failed before being closed.
+    pub async fn close(mut self) -> Result<()> {
+        self.handle.abort();
+        match (&mut self.handle).await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for PortForward {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl Sandbox {
+    /// Tunnels for the ports opened with `SandboxBuilder::open_port`, keyed by container port.
+    /// Waits until the tunnels are ready.
+    pub async fn tunnels(&mut self) -> Result<HashMap<u16, Tunnel>> {
+        loop {
+            let req_msg = client::SandboxGetTunnelsRequest {
+                sandbox_id: self.sandbox_id.clone(),
+                timeout: WAIT_POLL_SECS,
+            };
+            let client = self.client_mut();
+            let req = client.make_request(req_msg);
+            let resp = client.stub.sandbox_get_tunnels(req).await?.into_inner();
+            match resp.result {
+                Some(result) if result.status() == GenericStatus::Timeout => continue,
+                Some(result)
+                    if !matches!(
+                        result.status(),
+                        GenericStatus::Success | GenericStatus::Unspecified
+                    ) =>
+                {
+                    return Err(anyhow!(
+                        "failed to get tunnels: {:?} {}",
+                        result.status(),
+                        result.exception
+                    ))
+                }
+                _ => {}
+            }
+            return Ok(resp
+                .tunnels
+                .into_iter()
+                .map(|t| (t.container_port as u16, Tunnel::from(t)))
+                .collect());
+        }
+    }
+
+    /// Listen on `local_addr` and proxy each connection to the tunnel for `container_port`,
+    /// over raw TCP if the port was opened with `unencrypted` and over TLS otherwise.
+    pub async fn forward_local(
+        &mut self,
+        container_port: u16,
+        local_addr: impl ToSocketAddrs,
+    ) -> Result<PortForward> {
+        let tunnel = self
+            .tunnels()
+            .await?
+            .remove(&container_port)
+            .ok_or_else(|| anyhow!("port {} is not open in the sandbox", container_port))?;
+        let listener = TcpListener::bind(local_addr).await?;
+        let connector = match tunnel.tcp_socket() {
+            Some(_) => None,
+            None => Some(tls_connector()),
+        };
+
+        spawn_forward(listener, tunnel, connector)
+    }
+}
+
+/// Accept connections on `listener` and proxy each one to `tunnel` on its own task.
+fn spawn_forward(
+    listener: TcpListener,
+    tunnel: Tunnel,
+    connector: Option<TlsConnector>,
+) -> Result<PortForward> {
+    let local_addr = listener.local_addr()?;
+    let (errors_tx, errors) = mpsc::unbounded_channel();
+    let handle = tokio::spawn(async move {
+        loop {
+            let (inbound, peer) = listener.accept().await?;
+            let tunnel = tunnel.clone();
+            let connector = connector.clone();
+            let errors_tx = errors_tx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy(inbound, &tunnel, connector).await {
+                    let _ = errors_tx.send(e.context(format!(
+                        "forwarding {} to port {}",
+                        peer, tunnel.container_port
+                    )));
+                }
+            });
+        }
+    });
+    Ok(PortForward {
+        local_addr,
+        handle,
+        errors,
+    })
+}
+
+async fn proxy(
+    mut inbound: TcpStream,
+    tunnel: &Tunnel,
+    connector: Option<TlsConnector>,
+) -> Result<()> {
+    match (tunnel.tcp_socket(), connector) {
+        (Some((host, port)), _) => {
+            let outbound = TcpStream::connect((host, port)).await?;
+            pipe(&mut inbound, outbound).await
+        }
+        (None, Some(connector)) => {
+            let tcp = TcpStream::connect((tunnel.host.as_str(), tunnel.port)).await?;
+            let server_name = ServerName::try_from(tunnel.host.as_str())?;
+            let outbound = connector.connect(server_name, tcp).await?;
+            pipe(&mut inbound, outbound).await
+        }
+        (None, None) => Err(anyhow!("no TLS connector for tunnel {}", tunnel.host)),
+    }
+}
+
+async fn pipe<S>(inbound: &mut TcpStream, mut outbound: S) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    copy_bidirectional(inbound, &mut outbound).await?;
+    Ok(())
+}
+
+fn tls_connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn tcp_tunnel(port: u16) -> Tunnel {
+        Tunnel {
+            container_port: 8000,
+            host: "tunnel.example".to_string(),
+            port: 443,
+            unencrypted_host: Some("127.0.0.1".to_string()),
+            unencrypted_port: Some(port),
+        }
+    }
+
+    #[tokio::test]
+    async fn forwards_tcp_connections() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut conn, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&buf).await.unwrap();
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let forward = spawn_forward(listener, tcp_tunnel(upstream_port), None).unwrap();
+        let mut conn = TcpStream::connect(forward.local_addr()).await.unwrap();
+        conn.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        conn.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        forward.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_failed_connections() {
+        // Reserve a port, then free it so connecting upstream is refused.
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut forward = spawn_forward(listener, tcp_tunnel(closed_port), None).unwrap();
+        let _conn = TcpStream::connect(forward.local_addr()).await.unwrap();
+        let err = forward.next_error().await.unwrap();
+        assert!(err.to_string().contains("to port 8000"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn close_stops_listening() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let forward = spawn_forward(listener, tcp_tunnel(1), None).unwrap();
+        let addr = forward.local_addr();
+        forward.close().await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::client::ModalClient;
+use crate::proto::modal::client;
+use crate::sandbox::Sandbox;
+
+/// Number of Sandboxes sampled concurrently by a `UsageMeter`.
+const SAMPLE_CONCURRENCY: usize = 8;
+
+/// Resources a Sandbox has consumed since it started.
+///
+/// Each field is a resource-time integral: `cpu` is CPU-core time (one core busy for one
+/// second counts one second), `memory` is GiB time, and `gpu` is GPU time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub cpu: Duration,
+    pub memory: Duration,
+    pub gpu: Duration,
+    pub gpu_type: Option<String>,
+}
+
+impl ResourceUsage {
+    /// Usage accrued between `earlier` and `self`.
+    pub fn since(&self, earlier: &ResourceUsage) -> ResourceUsage {
+        ResourceUsage {
+            cpu: self.cpu.saturating_sub(earlier.cpu),
+            memory: self.memory.saturating_sub(earlier.memory),
+            gpu: self.gpu.saturating_sub(earlier.gpu),
+            gpu_type: self.gpu_type.clone(),
+        }
+    }
+
+    /// Whether no resources were used.
+    pub fn is_zero(&self) -> bool {
+        self.cpu.is_zero() && self.memory.is_zero() && self.gpu.is_zero()
+    }
+}
+
+impl From<client::SandboxGetResourceUsageResponse> for ResourceUsage {
+    fn from(resp: client::SandboxGetResourceUsageResponse) -> Self {
+        ResourceUsage {
+            cpu: Duration::from_nanos(resp.cpu_core_nanosecs),
+            memory: Duration::from_nanos(resp.mem_gib_nanosecs),
+            gpu: Duration::from_nanos(resp.gpu_nanosecs),
+            gpu_type: resp.gpu_type,
+        }
+    }
+}
+
+impl Sandbox {
+    /// Resources the Sandbox has consumed so far.
+    pub async fn resource_usage(&mut self) -> Result<ResourceUsage> {
+        let req_msg = client::SandboxGetResourceUsageRequest {
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        let client = self.client_mut();
+        let req = client.make_request(req_msg);
+        let resp = client
+            .stub
+            .sandbox_get_resource_usage(req)
+            .await?
+            .into_inner();
+        Ok(resp.into())
+    }
+}
+
+/// Usage reported by a `UsageMeter` for one Sandbox.
+#[derive(Clone, Debug)]
+pub struct UsageDelta {
+    pub sandbox_id: String,
+    /// Usage since the previous report for this Sandbox.
+    pub delta: ResourceUsage,
+    /// Usage since the Sandbox started.
+    pub total: ResourceUsage,
+}
+
+type UsageCallback = Arc<dyn Fn(UsageDelta) + Send + Sync>;
+
+/// Samples the resource usage of a set of Sandboxes periodically and reports what each used
+/// since its previous sample through a callback. The first report for a Sandbox covers all
+/// of its usage so far, so every nanosecond is reported exactly once.
+///
+/// The callback runs without any of the meter's locks held, so it may call back into the
+/// meter; it may be called concurrently for different Sandboxes.
+///
+/// Sampling stops when the meter is dropped.
+pub struct UsageMeter {
+    state: Arc<MeterState>,
+    handle: JoinHandle<()>,
+}
+
+struct MeterState {
+    client: ModalClient,
+    /// Last reported totals by Sandbox id.
+    sandboxes: Mutex<HashMap<String, ResourceUsage>>,
+    on_usage: UsageCallback,
+}
+
+impl UsageMeter {
+    /// Start sampling every `interval`, calling `on_usage` for each Sandbox with new usage.
+    pub fn start<F>(client: &ModalClient, interval: Duration, on_usage: F) -> UsageMeter
+    where
+        F: Fn(UsageDelta) + Send + Sync + 'static,
+    {
+        let state = Arc::new(MeterState {
+            client: client.clone(),
+            sandboxes: Mutex::new(HashMap::new()),
+            on_usage: Arc::new(on_usage),
+        });
+        let handle = tokio::spawn({
+            let state = state.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    ticker.tick().await;
+                    state.sample_all().await;
+                }
+            }
+        });
+        UsageMeter { state, handle }
+    }
+
+    /// Start metering a Sandbox.
+    pub fn track(&self, sandbox_id: &str) {
+        self.state
+            .sandboxes
+            .lock()
+            .unwrap()
+            .entry(sandbox_id.to_string())
+            .or_default();
+    }
+
+    /// Report the Sandbox's final usage and stop metering it. If the final sample fails,
+    /// the Sandbox stays tracked so its remaining usage is still reported, and the error is
+    /// returned; call `untrack` again to retry.
+    pub async fn untrack(&self, sandbox_id: &str) -> Result<()> {
+        self.state.sample(sandbox_id).await?;
+        self.state.sandboxes.lock().unwrap().remove(sandbox_id);
+        Ok(())
+    }
+
+    /// Sample every tracked Sandbox now instead of waiting for the next tick.
+    pub async fn sample_now(&self) {
+        self.state.sample_all().await;
+    }
+
+    /// Ids of the Sandboxes being metered.
+    pub fn tracked(&self) -> Vec<String> {
+        self.state
+            .sandboxes
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for UsageMeter {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl MeterState {
+    async fn sample_all(&self) {
+        let ids = self
+            .sandboxes
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        stream::iter(ids)
+            .for_each_concurrent(SAMPLE_CONCURRENCY, |sandbox_id| async move {
+                // A failed sample is retried on the next tick; the delta then covers both
+                // intervals, so no usage is lost.
+                let _ = self.sample(&sandbox_id).await;
+            })
+            .await;
+    }
+
+    async fn sample(&self, sandbox_id: &str) -> Result<()> {
+        let total = Sandbox::from_id(&self.client, sandbox_id)
+            .resource_usage()
+            .await?;
+        // Bind the result so the lock is released before the callback runs.
+        let usage = record(&mut self.sandboxes.lock().unwrap(), sandbox_id, total);
+        if let Some(usage) = usage {
+            (self.on_usage)(usage);
+        }
+        Ok(())
+    }
+}
+
+/// Store `total` as the tracked Sandbox's latest sample, returning the usage to report, if any.
+fn record(
+    sandboxes: &mut HashMap<String, ResourceUsage>,
+    sandbox_id: &str,
+    total: ResourceUsage,
+) -> Option<UsageDelta> {
+    let previous = sandboxes.get_mut(sandbox_id)?;
+    let delta = total.since(previous);
+    if delta.is_zero() {
+        return None;
+    }
+    *previous = total.clone();
+    Some(UsageDelta {
+        sandbox_id: sandbox_id.to_string(),
+        delta,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu_secs: u64) -> ResourceUsage {
+        ResourceUsage {
+            cpu: Duration::from_secs(cpu_secs),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_usage_since_previous_sample() {
+        let mut sandboxes = HashMap::new();
+        assert!(
+            record(&mut sandboxes, "sb-1", usage(5)).is_none(),
+            "untracked"
+        );
+
+        sandboxes.insert("sb-1".to_string(), ResourceUsage::default());
+        let first = record(&mut sandboxes, "sb-1", usage(5)).unwrap();
+        assert_eq!(first.delta, usage(5));
+        assert!(
+            record(&mut sandboxes, "sb-1", usage(5)).is_none(),
+            "no new usage"
+        );
+        let second = record(&mut sandboxes, "sb-1", usage(8)).unwrap();
+        assert_eq!(second.delta, usage(3));
+        assert_eq!(second.total, usage(8));
+    }
+}
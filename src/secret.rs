@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::client::ModalClient;
+use crate::pagination::list_all;
+use crate::proto::modal::client;
+
+/// Secrets management API, returned by `ModalClient::secrets`.
+#[derive(Clone)]
+pub struct Secrets {
+    client: ModalClient,
+}
+
+/// A Secret in the environment, as returned by `Secrets::list`.
+#[derive(Clone, Debug)]
+pub struct SecretInfo {
+    pub name: String,
+    pub secret_id: String,
+    /// Creation time in seconds since the Unix epoch.
+    pub created_at: f64,
+    /// Last use in seconds since the Unix epoch, or 0 if never used.
+    pub last_used_at: f64,
+}
+
+impl ModalClient {
+    /// Access the Secrets management API.
+    pub fn secrets(&self) -> Secrets {
+        Secrets {
+            client: self.clone(),
+        }
+    }
+}
+
+impl Secrets {
+    /// Create a Secret named `name` holding `env`, returning its id.
+    /// Fails if the Secret already exists unless `overwrite` is set.
+    pub async fn create(
+        &mut self,
+        name: &str,
+        env: HashMap<String, String>,
+        overwrite: bool,
+    ) -> Result<String> {
+        let object_creation_type = if overwrite {
+            client::ObjectCreationType::CreateOverwriteIfExists
+        } else {
+            client::ObjectCreationType::CreateFailIfExists
+        };
+        self.get_or_create(name, object_creation_type, env, vec![])
+            .await
+    }
+
+    /// Create a Secret named `name` from the `KEY=VALUE` lines of a `.env` file.
+    pub async fn create_from_dotenv(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+        overwrite: bool,
+    ) -> Result<String> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("failed to read '{}': {}", path.display(), e))?;
+        let env = parse_dotenv(&contents)?;
+        self.create(name, env, overwrite).await
+    }
+
+    /// Look up a Secret by name, returning its id. Fails if the Secret does not define every key
+    /// in `required_keys`.
+    pub async fn lookup(&mut self, name: &str, required_keys: &[&str]) -> Result<String> {
+        let required_keys = required_keys.iter().map(|k| k.to_string()).collect();
+        self.get_or_create(
+            name,
+            client::ObjectCreationType::Unspecified,
+            HashMap::new(),
+            required_keys,
+        )
+        .await
+    }
+
+    /// List the Secrets in the default environment.
+    pub async fn list(&mut self) -> Result<Vec<SecretInfo>> {
+        let items = list_all(
+            |pagination| {
+                let mut client = self.client.clone();
+                async move {
+                    let req_msg = client::SecretListRequest {
+                        environment_name: String::new(),
+                        pagination: Some(pagination),
+                    };
+                    let req = client.make_request(req_msg);
+                    let resp = client.stub.secret_list(req).await?.into_inner();
+                    Ok(resp.items)
+                }
+            },
+            |item| item.created_at,
+        )
+        .await?;
+        Ok(items
+            .into_iter()
+            .map(|item| SecretInfo {
+                name: item.label,
+                secret_id: item.secret_id,
+                created_at: item.created_at,
+                last_used_at: item.last_used_at,
+            })
+            .collect())
+    }
+
+    /// Delete the Secret named `name`.
+    pub async fn delete(&mut self, name: &str) -> Result<()> {
+        let secret_id = self.lookup(name, &[]).await?;
+        let req_msg = client::SecretDeleteRequest { secret_id };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.secret_delete(req).await?;
+        Ok(())
+    }
+
+    async fn get_or_create(
+        &mut self,
+        name: &str,
+        object_creation_type: client::ObjectCreationType,
+        env_dict: HashMap<String, String>,
+        required_keys: Vec<String>,
+    ) -> Result<String> {
+        let req_msg = client::SecretGetOrCreateRequest {
+            deployment_name: name.to_string(),
+            environment_name: String::new(),
+            object_creation_type: object_creation_type as i32,
+            env_dict,
+            app_id: String::new(),
+            required_keys,
+        };
+        let req = self.client.make_request(req_msg);
+        let resp = match self.client.stub.secret_get_or_create(req).await {
+            Ok(resp) => resp.into_inner(),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                return Err(anyhow!("secret '{}' not found: {}", name, status.message()));
+            }
+            Err(status) => return Err(status.into()),
+        };
+        if resp.secret_id.is_empty() {
+            return Err(anyhow!("secret '{}' not found", name));
+        }
+        Ok(resp.secret_id)
+    }
+}
+
+/// Parse `.env` contents: `KEY=VALUE` lines, optionally prefixed with `export`, with `#`
+/// comments and single- or double-quoted values.
+fn parse_dotenv(contents: &str) -> Result<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    for (lineno, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid .env line {}: expected KEY=VALUE", lineno + 1))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow!("invalid .env line {}: empty key", lineno + 1));
+        }
+        let value = value.trim();
+        let value = if let Some(inner) = value.strip_prefix('"') {
+            let end = closing_double_quote(inner)
+                .ok_or_else(|| anyhow!("invalid .env line {}: unterminated quote", lineno + 1))?;
+            check_after_quote(&inner[end + 1..], lineno)?;
+            unescape_double_quoted(&inner[..end])
+        } else if let Some(inner) = value.strip_prefix('\'') {
+            let end = inner
+                .find('\'')
+                .ok_or_else(|| anyhow!("invalid .env line {}: unterminated quote", lineno + 1))?;
+            check_after_quote(&inner[end + 1..], lineno)?;
+            inner[..end].to_string()
+        } else {
+            match value.find(" #") {
+                Some(idx) => value[..idx].trim_end().to_string(),
+                None => value.to_string(),
+            }
+        };
+        env.insert(key.to_string(), value);
+    }
+    Ok(env)
+}
+
+/// Byte offset of the first unescaped `"` in the body of a double-quoted value.
+fn closing_double_quote(inner: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Only a comment may follow a quoted value.
+fn check_after_quote(rest: &str, lineno: usize) -> Result<()> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "invalid .env line {}: unexpected text after quoted value",
+            lineno + 1
+        ))
+    }
+}
+
+/// Resolve the `\n`, `\"` and `\\` escapes allowed in double-quoted `.env` values.
+fn unescape_double_quoted(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> HashMap<String, String> {
+        parse_dotenv(contents).unwrap()
+    }
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_plain_assignments() {
+        let cases: &[(&str, &[(&str, &str)])] = &[
+            ("", &[]),
+            ("A=1", &[("A", "1")]),
+            ("A=1\nB=two words", &[("A", "1"), ("B", "two words")]),
+            ("  A  =  1  ", &[("A", "1")]),
+            ("A=", &[("A", "")]),
+            ("A=x=y", &[("A", "x=y")]),
+            ("A=1\r\nB=2\r\n", &[("A", "1"), ("B", "2")]),
+            ("A=1\nA=2", &[("A", "2")]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input), env(expected), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn strips_export_prefix() {
+        assert_eq!(parse("export A=1"), env(&[("A", "1")]));
+        assert_eq!(parse("export   A=1"), env(&[("A", "1")]));
+        // A key that merely starts with "export" is kept.
+        assert_eq!(parse("exported=1"), env(&[("exported", "1")]));
+    }
+
+    #[test]
+    fn skips_comments() {
+        let cases: &[(&str, &[(&str, &str)])] = &[
+            ("# comment\nA=1", &[("A", "1")]),
+            ("   # indented comment\n\nA=1", &[("A", "1")]),
+            ("A=1 # trailing", &[("A", "1")]),
+            ("URL=http://x/#anchor", &[("URL", "http://x/#anchor")]),
+            ("A=\"1 # kept\" # dropped", &[("A", "1 # kept")]),
+            ("A='1 # kept' # dropped", &[("A", "1 # kept")]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input), env(expected), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn handles_quoting_and_escapes() {
+        let cases: &[(&str, &str)] = &[
+            (r#"A="hello world""#, "hello world"),
+            (r#"A="""#, ""),
+            (r#"A="line1\nline2""#, "line1\nline2"),
+            (r#"A="say \"hi\"""#, "say \"hi\""),
+            (r#"A="back\\slash""#, "back\\slash"),
+            (r#"A="ends\\""#, "ends\\"),
+            (r#"A='no \n escapes'"#, "no \\n escapes"),
+            (r#"A='has "double" quotes'"#, "has \"double\" quotes"),
+            (r#"A="  padded  ""#, "  padded  "),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input), env(&[("A", expected)]), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for input in [
+            "NOEQUALS",
+            "=value",
+            "A=\"unterminated",
+            "A='unterminated",
+            "A=\"quoted\" trailing",
+            "A='a' 'b'",
+            // The closing quote is escaped.
+            r#"A="trailing\""#,
+        ] {
+            assert!(parse_dotenv(input).is_err(), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn errors_report_the_line_number() {
+        let err = parse_dotenv("A=1\n\nBROKEN").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{anyhow, Result};
+
+use crate::client::ModalClient;
+use crate::sandbox_exec::{router_exec, ExecOptions, Process, RouterStub};
+
+impl ModalClient {
+    /// Run a command inside the running container of task `task_id`, e.g. a Function
+    /// container, with stdout and stderr piped back.
+    pub async fn task_exec<I, S>(&mut self, task_id: &str, command: I) -> Result<Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.task_exec_with(task_id, command, ExecOptions::default())
+            .await
+    }
+
+    /// Run a command inside the container of task `task_id` with the given options.
+    pub async fn task_exec_with<I, S>(
+        &mut self,
+        task_id: &str,
+        command: I,
+        opts: ExecOptions,
+    ) -> Result<Process>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let command: Vec<String> = command.into_iter().map(Into::into).collect();
+        if command.is_empty() {
+            return Err(anyhow!("exec requires a command"));
+        }
+        router_exec(self, task_id, RouterStub::task, command, opts).await
+    }
+}
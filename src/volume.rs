@@ -0,0 +1,571 @@
+use anyhow::{anyhow, Result};
+use futures::stream::BoxStream;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::client::ModalClient;
+use crate::local_fs::{file_mode, hash_blocks, join_remote, relative_slash_path, walk_files};
+use crate::pagination::list_all;
+use crate::proto::modal::client;
+use crate::proto::modal::client::volume_put_files2_request as put_files2;
+
+/// Number of block URLs downloaded concurrently by the read helpers.
+const DOWNLOAD_CONCURRENCY: usize = 8;
+
+/// Number of missing blocks uploaded concurrently by the put helpers.
+const UPLOAD_CONCURRENCY: usize = 8;
+
+/// Number of `VolumePutFiles2` calls made before giving up on blocks the server keeps
+/// reporting as missing.
+const MAX_PUT_ATTEMPTS: u32 = 5;
+
+/// Size of the content-addressed blocks used by `VolumePutFiles2`.
+pub(crate) const BLOCK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// A handle to a named Modal Volume.
+///
+/// Namespace operations pick the v1 or v2 RPCs based on the volume's `VolumeFsVersion`; an
+/// unspecified version is treated as v1.
+#[derive(Clone)]
+pub struct Volume {
+    pub volume_id: String,
+    pub version: client::VolumeFsVersion,
+    client: ModalClient,
+}
+
+/// A file or directory inside a Volume, as returned by `Volume::list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumeEntry {
+    pub path: String,
+    pub file_type: VolumeEntryType,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+    pub size: u64,
+}
+
+/// The kind of a `VolumeEntry`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VolumeEntryType {
+    Unspecified,
+    File,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+}
+
+/// A Volume in the environment, as returned by `ModalClient::volume_list`.
+#[derive(Clone, Debug)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub volume_id: String,
+    pub version: client::VolumeFsVersion,
+    /// Creation time in seconds since the Unix epoch.
+    pub created_at: f64,
+}
+
+impl From<client::FileEntry> for VolumeEntry {
+    fn from(entry: client::FileEntry) -> Self {
+        use client::file_entry::FileType;
+        let file_type = match entry.r#type() {
+            FileType::Unspecified => VolumeEntryType::Unspecified,
+            FileType::File => VolumeEntryType::File,
+            FileType::Directory => VolumeEntryType::Directory,
+            FileType::Symlink => VolumeEntryType::Symlink,
+            FileType::Fifo => VolumeEntryType::Fifo,
+            FileType::Socket => VolumeEntryType::Socket,
+        };
+        VolumeEntry {
+            path: entry.path,
+            file_type,
+            mtime: entry.mtime,
+            size: entry.size,
+        }
+    }
+}
+
+impl ModalClient {
+    /// List the Volumes in the default environment.
+    pub async fn volume_list(&mut self) -> Result<Vec<VolumeInfo>> {
+        let items = list_all(
+            |pagination| {
+                let mut client = self.clone();
+                async move {
+                    let req_msg = client::VolumeListRequest {
+                        environment_name: String::new(),
+                        pagination: Some(pagination),
+                    };
+                    let req = client.make_request(req_msg);
+                    let resp = client.stub.volume_list(req).await?.into_inner();
+                    Ok(resp.items)
+                }
+            },
+            |item| item.created_at,
+        )
+        .await?;
+        Ok(items
+            .into_iter()
+            .map(|item| {
+                let version = item
+                    .metadata
+                    .as_ref()
+                    .map(|m| m.version())
+                    .unwrap_or(client::VolumeFsVersion::Unspecified);
+                VolumeInfo {
+                    name: item.label,
+                    volume_id: item.volume_id,
+                    version,
+                    created_at: item.created_at,
+                }
+            })
+            .collect())
+    }
+}
+
+impl Volume {
+    /// Look up a Volume by name, creating it if `create_if_missing` is set.
+    pub async fn from_name(
+        client: &mut ModalClient,
+        name: &str,
+        create_if_missing: bool,
+    ) -> Result<Volume> {
+        let object_creation_type = if create_if_missing {
+            client::ObjectCreationType::CreateIfMissing
+        } else {
+            client::ObjectCreationType::Unspecified
+        };
+        let req_msg = client::VolumeGetOrCreateRequest {
+            deployment_name: name.to_string(),
+            environment_name: String::new(),
+            object_creation_type: object_creation_type as i32,
+            app_id: String::new(),
+            version: client::VolumeFsVersion::Unspecified as i32,
+        };
+        let req = client.make_request(req_msg);
+        let resp = client.stub.volume_get_or_create(req).await?.into_inner();
+        if resp.volume_id.is_empty() {
+            return Err(anyhow!("volume not found"));
+        }
+
+        let version = resp
+            .metadata
+            .as_ref()
+            .map(|m| m.version())
+            .unwrap_or_else(|| resp.version());
+
+        Ok(Volume {
+            volume_id: resp.volume_id,
+            version,
+            client: client.clone(),
+        })
+    }
+
+    fn is_v2(&self) -> bool {
+        self.version == client::VolumeFsVersion::V2
+    }
+
+    /// Stream the entries under `path`. With `recursive`, descends into subdirectories;
+    /// `max_entries` caps the number of entries returned.
+    pub async fn list(
+        &mut self,
+        path: &str,
+        recursive: bool,
+        max_entries: Option<u32>,
+    ) -> Result<impl Stream<Item = Result<VolumeEntry>>> {
+        let batches: BoxStream<'static, Result<Vec<client::FileEntry>>> = if self.is_v2() {
+            let req_msg = client::VolumeListFiles2Request {
+                volume_id: self.volume_id.clone(),
+                path: path.to_string(),
+                recursive,
+                max_entries,
+            };
+            let req = self.client.make_request(req_msg);
+            let stream = self.client.stub.volume_list_files2(req).await?.into_inner();
+            stream
+                .map_ok(|batch| batch.entries)
+                .map_err(anyhow::Error::from)
+                .boxed()
+        } else {
+            let req_msg = client::VolumeListFilesRequest {
+                volume_id: self.volume_id.clone(),
+                path: path.to_string(),
+                recursive,
+                max_entries,
+            };
+            let req = self.client.make_request(req_msg);
+            let stream = self.client.stub.volume_list_files(req).await?.into_inner();
+            stream
+                .map_ok(|batch| batch.entries)
+                .map_err(anyhow::Error::from)
+                .boxed()
+        };
+
+        Ok(batches
+            .map_ok(|entries| stream::iter(entries.into_iter().map(|e| Ok(e.into()))))
+            .try_flatten())
+    }
+
+    /// Copy `src_paths` to `dst_path`. Directories are only copied with `recursive`.
+    pub async fn copy(
+        &mut self,
+        src_paths: &[&str],
+        dst_path: &str,
+        recursive: bool,
+    ) -> Result<()> {
+        let src_paths = src_paths.iter().map(|p| p.to_string()).collect();
+        if self.is_v2() {
+            let req_msg = client::VolumeCopyFiles2Request {
+                volume_id: self.volume_id.clone(),
+                src_paths,
+                dst_path: dst_path.to_string(),
+                recursive,
+            };
+            let req = self.client.make_request(req_msg);
+            self.client.stub.volume_copy_files2(req).await?;
+        } else {
+            let req_msg = client::VolumeCopyFilesRequest {
+                volume_id: self.volume_id.clone(),
+                src_paths,
+                dst_path: dst_path.to_string(),
+                recursive,
+            };
+            let req = self.client.make_request(req_msg);
+            self.client.stub.volume_copy_files(req).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a file, or a directory and its contents with `recursive`.
+    pub async fn remove(&mut self, path: &str, recursive: bool) -> Result<()> {
+        if self.is_v2() {
+            let req_msg = client::VolumeRemoveFile2Request {
+                volume_id: self.volume_id.clone(),
+                path: path.to_string(),
+                recursive,
+            };
+            let req = self.client.make_request(req_msg);
+            self.client.stub.volume_remove_file2(req).await?;
+        } else {
+            let req_msg = client::VolumeRemoveFileRequest {
+                volume_id: self.volume_id.clone(),
+                path: path.to_string(),
+                recursive,
+            };
+            let req = self.client.make_request(req_msg);
+            self.client.stub.volume_remove_file(req).await?;
+        }
+        Ok(())
+    }
+
+    /// Rename the Volume itself.
+    pub async fn rename(&mut self, new_name: &str) -> Result<()> {
+        let req_msg = client::VolumeRenameRequest {
+            volume_id: self.volume_id.clone(),
+            name: new_name.to_string(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.volume_rename(req).await?;
+        Ok(())
+    }
+
+    /// Commit pending changes so other containers can see them.
+    pub async fn commit(&mut self) -> Result<()> {
+        let req_msg = client::VolumeCommitRequest {
+            volume_id: self.volume_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.volume_commit(req).await?;
+        Ok(())
+    }
+
+    /// Reload the Volume to pick up changes committed elsewhere.
+    pub async fn reload(&mut self) -> Result<()> {
+        let req_msg = client::VolumeReloadRequest {
+            volume_id: self.volume_id.clone(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.volume_reload(req).await?;
+        Ok(())
+    }
+
+    /// Delete the Volume and all of its contents.
+    pub async fn delete(mut self) -> Result<()> {
+        #[allow(deprecated)]
+        let req_msg = client::VolumeDeleteRequest {
+            volume_id: self.volume_id.clone(),
+            environment_name: String::new(),
+        };
+        let req = self.client.make_request(req_msg);
+        self.client.stub.volume_delete(req).await?;
+        Ok(())
+    }
+
+    /// Read a whole file into memory.
+    pub async fn read_file(&mut self, path: &str) -> Result<Vec<u8>> {
+        self.read_range(path, 0, 0).await
+    }
+
+    /// Read `len` bytes starting at `start` into memory. A `len` of 0 reads to the end of the file.
+    pub async fn read_range(&mut self, path: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.read_range_into(path, start, len, &mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Stream a whole file into `writer`, returning the number of bytes written.
+    pub async fn read_file_into<W>(&mut self, path: &str, writer: &mut W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.read_range_into(path, 0, 0, writer).await
+    }
+
+    /// Stream `len` bytes starting at `start` into `writer`, returning the number of bytes
+    /// written. A `len` of 0 reads to the end of the file.
+    ///
+    /// The block URLs returned by `VolumeGetFile2` are fetched in parallel and written in order.
+    pub async fn read_range_into<W>(
+        &mut self,
+        path: &str,
+        start: u64,
+        len: u64,
+        writer: &mut W,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let resp = self.get_file(path, start, len).await?;
+        let written = self.download_blocks(resp.get_urls, writer).await?;
+        if written != resp.len {
+            return Err(anyhow!(
+                "short read of '{}': expected {} bytes, got {}",
+                path,
+                resp.len,
+                written
+            ));
+        }
+        Ok(written)
+    }
+
+    /// Download a whole file to `local_path`, creating parent directories as needed.
+    pub async fn download_file(&mut self, path: &str, local_path: impl AsRef<Path>) -> Result<u64> {
+        let local_path = local_path.as_ref();
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(local_path).await?;
+        self.read_file_into(path, &mut file).await
+    }
+
+    /// Resolve a file range to its block URLs.
+    pub(crate) async fn get_file(
+        &mut self,
+        path: &str,
+        start: u64,
+        len: u64,
+    ) -> Result<client::VolumeGetFile2Response> {
+        let req_msg = client::VolumeGetFile2Request {
+            volume_id: self.volume_id.clone(),
+            path: path.to_string(),
+            start,
+            len,
+        };
+        let req = self.client.make_request(req_msg);
+        match self.client.stub.volume_get_file2(req).await {
+            Ok(resp) => Ok(resp.into_inner()),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                Err(anyhow!("file not found: {}", path))
+            }
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    async fn download_blocks<W>(&self, urls: Vec<String>, writer: &mut W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let http = self.client.http.clone();
+        let mut blocks = stream::iter(urls)
+            .map(|url| {
+                let http = http.clone();
+                async move {
+                    let resp = http.get(&url).send().await?.error_for_status()?;
+                    resp.bytes().await
+                }
+            })
+            .buffered(DOWNLOAD_CONCURRENCY)
+            .map_err(anyhow::Error::from);
+
+        let mut written = 0u64;
+        while let Some(block) = blocks.try_next().await? {
+            writer.write_all(&block).await?;
+            written += block.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Upload a local file to `remote_path`, overwriting any existing file.
+    ///
+    /// The file is hashed locally in 8 MiB blocks and only blocks the volume does not already
+    /// hold are transferred. Unix permission bits are preserved.
+    pub async fn put_file(
+        &mut self,
+        local_path: impl AsRef<Path>,
+        remote_path: &str,
+    ) -> Result<()> {
+        self.put_files(vec![(
+            local_path.as_ref().to_path_buf(),
+            remote_path.to_string(),
+        )])
+        .await?;
+        Ok(())
+    }
+
+    /// Recursively upload a local directory under `remote_prefix`, overwriting existing files.
+    ///
+    /// See `put_file` for how file contents are deduplicated.
+    pub async fn put_dir(
+        &mut self,
+        local_dir: impl AsRef<Path>,
+        remote_prefix: &str,
+    ) -> Result<()> {
+        let local_dir = local_dir.as_ref().to_path_buf();
+        let remote_prefix = remote_prefix.trim_end_matches('/').to_string();
+        let files = tokio::task::spawn_blocking(move || -> Result<Vec<(PathBuf, String)>> {
+            let mut files = Vec::new();
+            for path in walk_files(&local_dir)? {
+                let rel = relative_slash_path(&local_dir, &path)?;
+                files.push((path, join_remote(&remote_prefix, &rel)));
+            }
+            Ok(files)
+        })
+        .await??;
+        self.put_files(files).await?;
+        Ok(())
+    }
+
+    /// Upload `(local_path, remote_path)` pairs with `VolumePutFiles2`, retrying with
+    /// `put_response`s until the volume reports no missing blocks. Returns the number of
+    /// bytes sent, which excludes blocks the volume already held.
+    pub(crate) async fn put_files(&mut self, files: Vec<(PathBuf, String)>) -> Result<u64> {
+        if self.version != client::VolumeFsVersion::V2 {
+            return Err(anyhow!("block uploads require a v2 volume"));
+        }
+        if files.is_empty() {
+            return Ok(0);
+        }
+
+        let uploads = stream::iter(files)
+            .map(|(local_path, remote_path)| {
+                tokio::task::spawn_blocking(move || hash_file(local_path, remote_path))
+            })
+            .buffered(UPLOAD_CONCURRENCY)
+            .map(|joined| joined.map_err(anyhow::Error::from).and_then(|r| r))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut files: Vec<put_files2::File> = uploads
+            .iter()
+            .map(|u| put_files2::File {
+                path: u.remote_path.clone(),
+                size: u.size,
+                blocks: u
+                    .block_hashes
+                    .iter()
+                    .map(|h| put_files2::Block {
+                        contents_sha256: h.to_vec(),
+                        put_response: None,
+                    })
+                    .collect(),
+                mode: u.mode,
+            })
+            .collect();
+
+        let mut sent = 0;
+        for _ in 0..MAX_PUT_ATTEMPTS {
+            let req_msg = client::VolumePutFiles2Request {
+                volume_id: self.volume_id.clone(),
+                files: files.clone(),
+                disallow_overwrite_existing_files: false,
+            };
+            let req = self.client.make_request(req_msg);
+            let resp = self.client.stub.volume_put_files2(req).await?.into_inner();
+            if resp.missing_blocks.is_empty() {
+                return Ok(sent);
+            }
+
+            let http = self.client.http.clone();
+            let responses = stream::iter(resp.missing_blocks)
+                .map(|missing| {
+                    let http = http.clone();
+                    let upload = uploads.get(missing.file_index as usize).cloned();
+                    async move {
+                        let upload = upload
+                            .ok_or_else(|| anyhow!("server referenced unknown file index"))?;
+                        let offset = missing.block_index * BLOCK_SIZE;
+                        let path = upload.local_path.clone();
+                        let len = BLOCK_SIZE.min(upload.size.saturating_sub(offset));
+                        let body =
+                            tokio::task::spawn_blocking(move || read_block(&path, offset, len))
+                                .await??;
+                        let put = http.put(&missing.put_url).body(body).send().await?;
+                        let put_response = put.error_for_status()?.bytes().await?;
+                        Ok::<_, anyhow::Error>((missing, put_response.to_vec(), len))
+                    }
+                })
+                .buffer_unordered(UPLOAD_CONCURRENCY)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            for (missing, put_response, len) in responses {
+                sent += len;
+                let block = files
+                    .get_mut(missing.file_index as usize)
+                    .and_then(|f| f.blocks.get_mut(missing.block_index as usize))
+                    .ok_or_else(|| anyhow!("server referenced unknown block index"))?;
+                block.put_response = Some(put_response);
+            }
+        }
+        Err(anyhow!(
+            "volume still reports missing blocks after {} attempts",
+            MAX_PUT_ATTEMPTS
+        ))
+    }
+}
+
+/// A local file prepared for `VolumePutFiles2`.
+#[derive(Clone)]
+struct FileUpload {
+    local_path: PathBuf,
+    remote_path: String,
+    size: u64,
+    mode: Option<u32>,
+    block_hashes: Vec<[u8; 32]>,
+}
+
+/// Hash a file in `BLOCK_SIZE` blocks.
+fn hash_file(local_path: PathBuf, remote_path: String) -> Result<FileUpload> {
+    let mut file = std::fs::File::open(&local_path)
+        .map_err(|e| anyhow!("failed to open '{}': {}", local_path.display(), e))?;
+    let metadata = file.metadata()?;
+    let block_hashes = hash_blocks(&mut file, BLOCK_SIZE)?;
+
+    Ok(FileUpload {
+        local_path,
+        remote_path,
+        size: metadata.len(),
+        mode: file_mode(&metadata),
+        block_hashes,
+    })
+}
+
+fn read_block(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0u8; len as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,341 @@
+use anyhow::Result;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::task::JoinHandle;
+
+use crate::volume::{Volume, BLOCK_SIZE};
+
+/// Number of blocks fetched ahead of the current position by default.
+const DEFAULT_READ_AHEAD: usize = 2;
+
+/// Number of already-read blocks kept in memory by default.
+const DEFAULT_CACHE_BLOCKS: usize = 4;
+
+/// Fetches `len` bytes starting at `start` of the file being read.
+type FetchRange = Arc<dyn Fn(u64, u64) -> BoxFuture<'static, Result<Bytes>> + Send + Sync>;
+
+/// A read-only file inside a Volume that fetches byte ranges on demand.
+///
+/// Reads are served from fixed-size blocks fetched with ranged `VolumeGetFile2` requests.
+/// Blocks after the current position are prefetched in the background, and a small number of
+/// recently read blocks are cached so short backward seeks do not hit the network.
+pub struct VolumeFile {
+    fetch: FetchRange,
+    path: String,
+    size: u64,
+    pos: u64,
+    block_size: u64,
+    read_ahead: usize,
+    cache_blocks: usize,
+    cache: HashMap<u64, Bytes>,
+    cache_order: VecDeque<u64>,
+    inflight: HashMap<u64, JoinHandle<Result<Bytes>>>,
+}
+
+impl Volume {
+    /// Open a file for random-access reads. See `VolumeFile`.
+    pub async fn open(&mut self, path: &str) -> Result<VolumeFile> {
+        let resp = self.get_file(path, 0, 0).await?;
+        let volume = self.clone();
+        let file_path = path.to_string();
+        let fetch: FetchRange = Arc::new(move |start, len| {
+            let mut volume = volume.clone();
+            let path = file_path.clone();
+            async move {
+                let mut buf = Vec::with_capacity(len as usize);
+                volume.read_range_into(&path, start, len, &mut buf).await?;
+                Ok(Bytes::from(buf))
+            }
+            .boxed()
+        });
+        Ok(VolumeFile::new(path, resp.size, fetch))
+    }
+}
+
+impl VolumeFile {
+    fn new(path: &str, size: u64, fetch: FetchRange) -> VolumeFile {
+        VolumeFile {
+            fetch,
+            path: path.to_string(),
+            size,
+            pos: 0,
+            block_size: BLOCK_SIZE,
+            read_ahead: DEFAULT_READ_AHEAD,
+            cache_blocks: DEFAULT_CACHE_BLOCKS,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            inflight: HashMap::new(),
+        }
+    }
+
+    /// Set the size of each ranged request. Defaults to 8 MiB.
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.reset();
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Set how many blocks past the current one are prefetched. Defaults to 2.
+    pub fn with_read_ahead(mut self, blocks: usize) -> Self {
+        self.read_ahead = blocks;
+        self
+    }
+
+    /// Set how many already-read blocks are kept in memory. Defaults to 4.
+    pub fn with_cache_blocks(mut self, blocks: usize) -> Self {
+        self.cache_blocks = blocks.max(1);
+        self
+    }
+
+    /// Total size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Path of the file inside the Volume.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.size.div_ceil(self.block_size)
+    }
+
+    fn reset(&mut self) {
+        for (_, handle) in self.inflight.drain() {
+            handle.abort();
+        }
+        self.cache.clear();
+        self.cache_order.clear();
+    }
+
+    /// Start a background fetch for `block` unless it is cached or already in flight.
+    fn prefetch(&mut self, block: u64) {
+        if block >= self.num_blocks()
+            || self.cache.contains_key(&block)
+            || self.inflight.contains_key(&block)
+        {
+            return;
+        }
+        let start = block * self.block_size;
+        let len = self.block_size.min(self.size - start);
+        let handle = tokio::spawn((self.fetch)(start, len));
+        self.inflight.insert(block, handle);
+    }
+
+    fn insert_cached(&mut self, block: u64, data: Bytes) {
+        let capacity = self.cache_blocks + self.read_ahead;
+        while self.cache_order.len() >= capacity {
+            if let Some(evicted) = self.cache_order.pop_front() {
+                self.cache.remove(&evicted);
+            }
+        }
+        self.cache.insert(block, data);
+        self.cache_order.push_back(block);
+    }
+}
+
+impl AsyncRead for VolumeFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos >= this.size || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let block = this.pos / this.block_size;
+        let window = block..=block + this.read_ahead as u64;
+        this.inflight.retain(|b, handle| {
+            let keep = window.contains(b);
+            if !keep {
+                handle.abort();
+            }
+            keep
+        });
+        for ahead in 0..=this.read_ahead as u64 {
+            this.prefetch(block + ahead);
+        }
+
+        if !this.cache.contains_key(&block) {
+            let handle = this
+                .inflight
+                .get_mut(&block)
+                .expect("block fetch was just scheduled");
+            let data = match Pin::new(handle).poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(joined) => {
+                    this.inflight.remove(&block);
+                    match joined {
+                        Ok(Ok(data)) => data,
+                        Ok(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+                        Err(e) => return Poll::Ready(Err(io::Error::other(e))),
+                    }
+                }
+            };
+            this.insert_cached(block, data);
+        }
+
+        let data = &this.cache[&block];
+        let offset = (this.pos - block * this.block_size) as usize;
+        let n = buf.remaining().min(data.len().saturating_sub(offset));
+        if n == 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "volume returned a short block",
+            )));
+        }
+        buf.put_slice(&data[offset..offset + n]);
+        this.pos += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for VolumeFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => this.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) => {
+                this.pos = target;
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+impl Drop for VolumeFile {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    /// A file of `size` bytes where byte `i` is `i % 251`, recording each fetched range.
+    fn fake_file(size: u64, fetches: Arc<Mutex<Vec<(u64, u64)>>>) -> VolumeFile {
+        let fetch: FetchRange = Arc::new(move |start, len| {
+            fetches.lock().unwrap().push((start, len));
+            let data: Vec<u8> = (start..start + len).map(|i| (i % 251) as u8).collect();
+            async move { Ok(Bytes::from(data)) }.boxed()
+        });
+        VolumeFile::new("/data", size, fetch)
+    }
+
+    fn expected(range: std::ops::Range<u64>) -> Vec<u8> {
+        range.map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn reads_across_block_boundaries() {
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let mut file = fake_file(25, fetches.clone()).with_block_size(10);
+        let mut out = Vec::new();
+        file.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, expected(0..25));
+
+        file.seek(SeekFrom::Start(7)).await.unwrap();
+        let mut buf = [0u8; 6];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf.to_vec(), expected(7..13));
+
+        let mut fetched = fetches.lock().unwrap().clone();
+        fetched.sort();
+        assert_eq!(fetched, vec![(0, 10), (10, 10), (20, 5)]);
+    }
+
+    #[tokio::test]
+    async fn seeks_relative_to_end_and_current() {
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let mut file = fake_file(25, fetches).with_block_size(10);
+
+        assert_eq!(file.seek(SeekFrom::End(-3)).await.unwrap(), 22);
+        let mut out = Vec::new();
+        file.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, expected(22..25));
+
+        assert_eq!(file.seek(SeekFrom::Current(-10)).await.unwrap(), 15);
+        let mut buf = [0u8; 2];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf.to_vec(), expected(15..17));
+        assert_eq!(file.seek(SeekFrom::Current(0)).await.unwrap(), 17);
+
+        assert!(file.seek(SeekFrom::Current(-18)).await.is_err());
+        assert!(file.seek(SeekFrom::End(-26)).await.is_err());
+        assert_eq!(file.seek(SeekFrom::Current(0)).await.unwrap(), 17);
+    }
+
+    #[tokio::test]
+    async fn reads_nothing_past_eof() {
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let mut file = fake_file(25, fetches.clone()).with_block_size(10);
+        assert_eq!(file.seek(SeekFrom::End(5)).await.unwrap(), 30);
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(file.seek(SeekFrom::Start(25)).await.unwrap(), 25);
+        assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+        assert!(fetches.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn prefetches_the_read_ahead_window() {
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let mut file = fake_file(50, fetches.clone())
+            .with_block_size(10)
+            .with_read_ahead(2);
+        let mut buf = [0u8; 1];
+        file.read_exact(&mut buf).await.unwrap();
+        let mut fetched = fetches.lock().unwrap().clone();
+        fetched.sort();
+        assert_eq!(fetched, vec![(0, 10), (10, 10), (20, 10)]);
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_cached_block_first() {
+        let fetches = Arc::new(Mutex::new(Vec::new()));
+        let mut file = fake_file(40, fetches.clone())
+            .with_block_size(10)
+            .with_read_ahead(0)
+            .with_cache_blocks(2);
+        let mut buf = [0u8; 1];
+        for block in 0..3 {
+            file.seek(SeekFrom::Start(block * 10)).await.unwrap();
+            file.read_exact(&mut buf).await.unwrap();
+        }
+        // Block 0 was evicted when block 2 arrived; blocks 1 and 2 are still cached.
+        for block in [2, 1, 0] {
+            file.seek(SeekFrom::Start(block * 10)).await.unwrap();
+            file.read_exact(&mut buf).await.unwrap();
+        }
+        assert_eq!(
+            *fetches.lock().unwrap(),
+            vec![(0, 10), (10, 10), (20, 10), (0, 10)]
+        );
+    }
+}
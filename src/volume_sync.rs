@@ -0,0 +1,365 @@
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use glob::{MatchOptions, Pattern};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::local_fs::{join_remote, relative_slash_path, walk_files};
+use crate::volume::{Volume, VolumeEntryType};
+
+/// Options for `Volume::sync_from_local` and `Volume::sync_to_local`.
+///
+/// `include` and `exclude` are glob patterns matched against paths relative to the synced
+/// directories, using `/` as the separator (`**` crosses directories, `*` does not). A path is
+/// synced if it matches any `include` pattern (or `include` is empty) and no `exclude` pattern.
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Delete files on the destination that do not exist on the source.
+    pub delete: bool,
+    /// Compute the report without transferring or deleting anything.
+    pub dry_run: bool,
+}
+
+/// What a sync did, or would do with `dry_run`. Paths are relative to the synced directories.
+#[derive(Clone, Debug, Default)]
+pub struct SyncReport {
+    pub transferred: Vec<String>,
+    pub deleted: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Bytes sent or received. Uploads only send blocks the volume does not already hold; with
+    /// `dry_run`, this is the total size of the files that would be transferred.
+    pub bytes: u64,
+}
+
+/// Size and modification time (seconds since the Unix epoch) of a synced file.
+#[derive(Clone, Copy, Debug)]
+struct FileStat {
+    size: u64,
+    mtime: u64,
+}
+
+/// Whether a source file must be transferred: it is missing at the destination, or its size
+/// or modification time differs. Equal sizes with different times are transferred rather than
+/// compared, since comparing would mean reading the remote file.
+fn changed(source: &FileStat, dest: Option<&FileStat>) -> bool {
+    dest.is_none_or(|d| d.size != source.size || d.mtime != source.mtime)
+}
+
+struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    fn new(opts: &SyncOptions) -> Result<Self> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|g| Pattern::new(g).map_err(|e| anyhow!("invalid glob '{}': {}", g, e)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            include: compile(&opts.include)?,
+            exclude: compile(&opts.exclude)?,
+        })
+    }
+
+    fn matches(&self, rel: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_with(rel, options)))
+            && !self.exclude.iter().any(|p| p.matches_with(rel, options))
+    }
+}
+
+impl Volume {
+    /// Make `remote_prefix` in the volume match the local directory `dir`.
+    ///
+    /// A file is uploaded when it is missing remotely or its size or modification time
+    /// differs. Uploads go through `put_files`, which hashes the local file and sends only the
+    /// blocks whose SHA-256 the volume does not already hold, so re-uploading an unchanged
+    /// file costs a local read and no transfer. The volume does not keep local modification
+    /// times, so files uploaded by an earlier sync are re-checked this way every time.
+    pub async fn sync_from_local(
+        &mut self,
+        dir: impl AsRef<Path>,
+        remote_prefix: &str,
+        opts: &SyncOptions,
+    ) -> Result<SyncReport> {
+        let filter = PathFilter::new(opts)?;
+        let dir = dir.as_ref().to_path_buf();
+        let local = local_files(&dir, &filter).await?;
+        let remote = self.remote_files(remote_prefix, &filter).await?;
+
+        let mut report = SyncReport::default();
+        let mut uploads = Vec::new();
+        for (rel, stat) in &local {
+            if changed(stat, remote.get(rel)) {
+                report.transferred.push(rel.clone());
+                report.bytes += stat.size;
+                uploads.push((dir.join(rel), join_remote(remote_prefix, rel)));
+            } else {
+                report.unchanged.push(rel.clone());
+            }
+        }
+        if opts.delete {
+            report.deleted = remote
+                .keys()
+                .filter(|rel| !local.contains_key(*rel))
+                .cloned()
+                .collect();
+        }
+        sort_report(&mut report);
+
+        if !opts.dry_run {
+            report.bytes = self.put_files(uploads).await?;
+            for rel in &report.deleted {
+                self.remove(&join_remote(remote_prefix, rel), false).await?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Make the local directory `dir` match `remote_prefix` in the volume.
+    ///
+    /// A file is downloaded when it is missing locally or its size or modification time
+    /// differs. Downloaded files get the remote modification time, so later syncs skip them
+    /// without reading either copy.
+    pub async fn sync_to_local(
+        &mut self,
+        remote_prefix: &str,
+        dir: impl AsRef<Path>,
+        opts: &SyncOptions,
+    ) -> Result<SyncReport> {
+        let filter = PathFilter::new(opts)?;
+        let dir = dir.as_ref().to_path_buf();
+        let local = if dir.exists() {
+            local_files(&dir, &filter).await?
+        } else {
+            HashMap::new()
+        };
+        let remote = self.remote_files(remote_prefix, &filter).await?;
+
+        let mut report = SyncReport::default();
+        for (rel, stat) in &remote {
+            if changed(stat, local.get(rel)) {
+                report.transferred.push(rel.clone());
+                report.bytes += stat.size;
+            } else {
+                report.unchanged.push(rel.clone());
+            }
+        }
+        if opts.delete {
+            report.deleted = local
+                .keys()
+                .filter(|rel| !remote.contains_key(*rel))
+                .cloned()
+                .collect();
+        }
+        sort_report(&mut report);
+
+        if !opts.dry_run {
+            for rel in &report.transferred {
+                let local_path = dir.join(rel);
+                self.download_file(&join_remote(remote_prefix, rel), &local_path)
+                    .await?;
+                set_mtime(local_path, remote[rel].mtime).await?;
+            }
+            for rel in &report.deleted {
+                tokio::fs::remove_file(dir.join(rel)).await?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Regular files under `remote_prefix`, keyed by path relative to it.
+    /// A missing prefix is treated as empty.
+    async fn remote_files(
+        &mut self,
+        remote_prefix: &str,
+        filter: &PathFilter,
+    ) -> Result<HashMap<String, FileStat>> {
+        let prefix = remote_prefix.trim_matches('/');
+        let entries = match self.list(prefix, true, None).await {
+            Ok(stream) => stream.try_collect::<Vec<_>>().await,
+            Err(e) => Err(e),
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) if is_not_found(&e) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut files = HashMap::new();
+        for entry in entries {
+            if entry.file_type != VolumeEntryType::File {
+                continue;
+            }
+            let path = entry.path.trim_start_matches('/');
+            let rel = if prefix.is_empty() {
+                path
+            } else {
+                match path.strip_prefix(prefix).and_then(|r| r.strip_prefix('/')) {
+                    Some(rest) => rest,
+                    None => continue,
+                }
+            };
+            if rel.is_empty() || !filter.matches(rel) {
+                continue;
+            }
+            files.insert(
+                rel.to_string(),
+                FileStat {
+                    size: entry.size,
+                    mtime: entry.mtime,
+                },
+            );
+        }
+        Ok(files)
+    }
+}
+
+/// Regular files under `dir`, keyed by `/`-separated path relative to it.
+async fn local_files(dir: &Path, filter: &PathFilter) -> Result<HashMap<String, FileStat>> {
+    let paths = tokio::task::spawn_blocking({
+        let dir = dir.to_path_buf();
+        move || walk_files(&dir)
+    })
+    .await??;
+
+    let mut files = HashMap::new();
+    for path in paths {
+        let rel = relative_slash_path(dir, &path)?;
+        if !filter.matches(&rel) {
+            continue;
+        }
+        let metadata = tokio::fs::metadata(&path).await?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        files.insert(
+            rel,
+            FileStat {
+                size: metadata.len(),
+                mtime,
+            },
+        );
+    }
+    Ok(files)
+}
+
+/// Set a local file's modification time to `mtime` seconds since the Unix epoch.
+async fn set_mtime(path: PathBuf, mtime: u64) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::options().write(true).open(&path)?;
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+        Ok(())
+    })
+    .await?
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<tonic::Status>()
+        .is_some_and(|s| s.code() == tonic::Code::NotFound)
+}
+
+fn sort_report(report: &mut SyncReport) {
+    report.transferred.sort();
+    report.deleted.sort();
+    report.unchanged.sort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(size: u64, mtime: u64) -> FileStat {
+        FileStat { size, mtime }
+    }
+
+    #[test]
+    fn changed_compares_size_and_mtime() {
+        let cases = [
+            (stat(10, 100), None, true),
+            (stat(10, 100), Some(stat(11, 100)), true),
+            (stat(10, 100), Some(stat(11, 50)), true),
+            (stat(10, 100), Some(stat(10, 100)), false),
+            // Either side being newer counts; the transfer itself skips identical blocks.
+            (stat(10, 100), Some(stat(10, 50)), true),
+            (stat(10, 50), Some(stat(10, 100)), true),
+            (stat(0, 0), Some(stat(0, 0)), false),
+        ];
+        for (source, dest, expected) in cases {
+            assert_eq!(
+                changed(&source, dest.as_ref()),
+                expected,
+                "{:?} vs {:?}",
+                source,
+                dest
+            );
+        }
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        PathFilter::new(&SyncOptions {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn path_filter_without_patterns_matches_everything() {
+        let f = filter(&[], &[]);
+        for path in ["a", "a/b/c.txt", ".hidden", "dir/.env"] {
+            assert!(f.matches(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn path_filter_star_does_not_cross_directories() {
+        let f = filter(&["*.txt"], &[]);
+        assert!(f.matches("a.txt"));
+        assert!(!f.matches("dir/a.txt"));
+
+        let f = filter(&["**/*.txt"], &[]);
+        assert!(f.matches("a.txt"));
+        assert!(f.matches("dir/sub/a.txt"));
+        assert!(!f.matches("dir/a.rs"));
+    }
+
+    #[test]
+    fn path_filter_exclude_wins_over_include() {
+        let f = filter(&["src/**"], &["**/*.tmp", "src/target/**"]);
+        assert!(f.matches("src/lib.rs"));
+        assert!(!f.matches("src/cache.tmp"));
+        assert!(!f.matches("src/target/debug/out"));
+        assert!(!f.matches("docs/readme.md"));
+    }
+
+    #[test]
+    fn path_filter_matches_dotfiles_and_is_case_sensitive() {
+        let f = filter(&["*"], &["*.LOG"]);
+        assert!(f.matches(".env"));
+        assert!(f.matches("run.log"));
+        assert!(!f.matches("RUN.LOG"));
+    }
+
+    #[test]
+    fn path_filter_rejects_invalid_globs() {
+        let opts = SyncOptions {
+            include: vec!["[".to_string()],
+            ..Default::default()
+        };
+        assert!(PathFilter::new(&opts).is_err());
+    }
+}